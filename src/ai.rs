@@ -4,27 +4,19 @@ use specs_derive::Component;
 
 use super::*;
 
-const BLOOD_COLOR: u32 = 0xAC_32_33;
-
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Destination(pub f64);
 
-#[derive(Component, Debug)]
-pub struct Ally;
-
-#[derive(Component, Debug)]
-pub struct Enemy;
-
 #[derive(Component, Debug)]
 pub struct Melee {
-    dmg: f64,
+    dmg: Damage,
     hitrate: f64,
 
     cooldown: f64,
 }
 
 impl Melee {
-    pub fn new(dmg: f64, hitrate: f64) -> Self {
+    pub fn new(dmg: Damage, hitrate: f64) -> Self {
         Melee {
             dmg,
             hitrate,
@@ -38,11 +30,14 @@ impl Melee {
 pub struct MeleeSystemData<'a> {
     entities: Entities<'a>,
     dt: Read<'a, DeltaTime>,
-    ally: ReadStorage<'a, Ally>,
-    enemy: ReadStorage<'a, Enemy>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
     pos: ReadStorage<'a, WorldPosition>,
     bb: ReadStorage<'a, BoundingBox>,
     state: ReadStorage<'a, UnitState>,
+    morale: ReadStorage<'a, Morale>,
+    buffs: ReadStorage<'a, Buffs>,
+    armor: ReadStorage<'a, Armor>,
     melee: WriteStorage<'a, Melee>,
     health: WriteStorage<'a, Health>,
     updater: Read<'a, LazyUpdate>,
@@ -54,109 +49,81 @@ impl<'a> System<'a> for MeleeSystem {
 
     fn run(&mut self, mut system_data: Self::SystemData) {
         let dt = system_data.dt.to_seconds();
+        let factions = &system_data.factions;
 
-        for (a, _, a_pos, a_bb, a_state) in (
+        for (a, a_faction, a_pos, a_bb, a_state, melee) in (
             &*system_data.entities,
-            &system_data.ally,
+            &system_data.faction,
             &system_data.pos,
             &system_data.bb,
             &system_data.state,
+            &mut system_data.melee,
         )
             .join()
         {
-            // Only fight between units with the melee state
+            // Only fight with the melee state, routing units don't fight back but can still be hit
+            // while they flee
             if *a_state != UnitState::Melee {
                 continue;
             }
 
+            // Attack the first unit of a hostile faction it touches
             let a_aabb = *a_bb + *a_pos.0;
-            for (e, _, e_pos, e_bb) in (
+            let target = (
                 &*system_data.entities,
-                &system_data.enemy,
+                &system_data.faction,
                 &system_data.pos,
                 &system_data.bb,
             )
                 .join()
-            {
-                // Only fight between units with the melee state
-                if *a_state != UnitState::Melee {
-                    continue;
-                }
-
-                let e_aabb = *e_bb + *e_pos.0;
-                if a_aabb.intersects(&*e_aabb) {
-                    {
-                        let a_melee: Option<&mut Melee> = system_data.melee.get_mut(a);
-                        if let Some(melee) = a_melee {
-                            melee.cooldown -= dt;
-                            if melee.cooldown <= 0.0 {
-                                if reduce_unit_health(
-                                    &system_data.entities,
-                                    e,
-                                    system_data.health.get_mut(e).unwrap(),
-                                    melee.dmg,
-                                ) {
-                                    // The enemy died
-                                    system_data.updater.insert(
-                                        system_data.entities.create(),
-                                        FloatingText {
-                                            text: "x".to_string(),
-                                            pos: e_pos.0,
-                                            time_alive: 2.0,
-                                        },
-                                    );
-                                }
-
-                                melee.cooldown = melee.hitrate;
-
-                                let blood = system_data.entities.create();
-                                system_data
-                                    .updater
-                                    .insert(blood, PixelParticle::new(BLOOD_COLOR, 10.0));
-                                system_data.updater.insert(blood, *e_pos);
-                                system_data
-                                    .updater
-                                    .insert(blood, Velocity::new(-10.0, -10.0));
-                            }
-                        }
-                    }
-                    {
-                        let e_melee: Option<&mut Melee> = system_data.melee.get_mut(e);
-                        if let Some(melee) = e_melee {
-                            melee.cooldown -= dt;
-                            if melee.cooldown <= 0.0 {
-                                if reduce_unit_health(
-                                    &system_data.entities,
-                                    a,
-                                    system_data.health.get_mut(a).unwrap(),
-                                    melee.dmg,
-                                ) {
-                                    // The ally died
-                                    system_data.updater.insert(
-                                        system_data.entities.create(),
-                                        FloatingText {
-                                            text: "x".to_string(),
-                                            pos: a_pos.0,
-                                            time_alive: 2.0,
-                                        },
-                                    );
-                                }
-
-                                melee.cooldown = melee.hitrate;
-
-                                let blood = system_data.entities.create();
-                                system_data
-                                    .updater
-                                    .insert(blood, PixelParticle::new(BLOOD_COLOR, 10.0));
-                                system_data.updater.insert(blood, *a_pos);
-                                system_data
-                                    .updater
-                                    .insert(blood, Velocity::new(-10.0, -10.0));
-                            }
-                        }
-                    }
+                .find(|(_, e_faction, e_pos, e_bb)| {
+                    factions.hostile(Some(a_faction), Some(*e_faction))
+                        && a_aabb.intersects(&*(**e_bb + *e_pos.0))
+                })
+                .map(|(e, _, e_pos, _)| (e, e_pos.0));
+            let (e, e_pos) = match target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            melee.cooldown -= dt;
+            if melee.cooldown > 0.0 {
+                continue;
+            }
+            melee.cooldown = melee.hitrate;
+
+            if let Some(health) = system_data.health.get_mut(e) {
+                let dmg = Damage(
+                    melee.dmg.0
+                        * morale_effectiveness(system_data.morale.get(a))
+                        * damage_factor(system_data.buffs.get(a)),
+                    melee.dmg.1,
+                );
+                if reduce_unit_health(
+                    &system_data.entities,
+                    e,
+                    health,
+                    system_data.armor.get(e),
+                    dmg,
+                ) {
+                    // The other unit died
+                    system_data.updater.insert(
+                        system_data.entities.create(),
+                        FloatingText {
+                            text: "x".to_string(),
+                            pos: e_pos,
+                            time_alive: 2.0,
+                        },
+                    );
                 }
             }
+
+            Emitter::spawn(
+                &system_data.entities,
+                &system_data.updater,
+                ParticleEffect { burst: 2, ..BLOOD },
+                e_pos,
+            );
         }
     }
 }
@@ -0,0 +1,58 @@
+use specs::prelude::*;
+use specs_derive::Component;
+
+/// What a hit is done with, armor protects better against some types than others.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DamageType {
+    /// Arrows and bolts.
+    Pierce,
+    /// Swords and axes.
+    Slash,
+    /// Clubs, trampling hooves and falling.
+    Blunt,
+    /// Bombs and mines.
+    Fire,
+    /// Catapult stones, made to break walls and engines instead of men.
+    Siege,
+}
+
+impl DamageType {
+    /// How many times bigger than its mask the hole is the damage leaves in the terrain, siege
+    /// weapons are made to break walls.
+    pub fn crater_scale(self) -> usize {
+        match self {
+            DamageType::Siege => 2,
+            DamageType::Pierce | DamageType::Slash | DamageType::Blunt | DamageType::Fire => 1,
+        }
+    }
+}
+
+/// The part of the damage of every type that's stopped, negative values make the unit take extra
+/// damage.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct Armor {
+    pub pierce: f64,
+    pub slash: f64,
+    pub blunt: f64,
+    pub fire: f64,
+    pub siege: f64,
+}
+
+impl Armor {
+    pub fn resistance(&self, damage_type: DamageType) -> f64 {
+        match damage_type {
+            DamageType::Pierce => self.pierce,
+            DamageType::Slash => self.slash,
+            DamageType::Blunt => self.blunt,
+            DamageType::Fire => self.fire,
+            DamageType::Siege => self.siege,
+        }
+    }
+}
+
+/// The damage that's left after the armor of the unit, units without armor take all of it.
+pub fn armored_damage(armor: Option<&Armor>, damage: f64, damage_type: DamageType) -> f64 {
+    let resistance = armor.map_or(0.0, |armor| armor.resistance(damage_type));
+
+    damage * (1.0 - resistance.min(1.0))
+}
@@ -41,6 +41,17 @@ const UNIT_HIT_SUSTAIN_DURATION: f32 = 0.005;
 #[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
 const UNIT_HIT_DECAY_DURATION: f32 = 0.14;
 
+#[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
+const EXPLOSION_VOLUME: f32 = 1.0;
+#[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
+const EXPLOSION_BASE_FREQ: f64 = 0.08;
+#[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
+const EXPLOSION_ATTACK_DURATION: f32 = 0.0;
+#[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
+const EXPLOSION_SUSTAIN_DURATION: f32 = 0.1;
+#[const_tweaker::tweak(min = 0.0, max = 1.0, step = 0.001)]
+const EXPLOSION_DECAY_DURATION: f32 = 0.4;
+
 /// Manages the audio.
 #[derive(Default)]
 pub struct Audio {
@@ -94,6 +105,19 @@ impl Audio {
         self.play(sample, *UNIT_HIT_VOLUME);
     }
 
+    /// Play a sound for an explosion.
+    pub fn play_explosion(&self) {
+        let mut sample = Sample::new();
+
+        sample.wave_type = WaveType::Noise;
+        sample.base_freq = *EXPLOSION_BASE_FREQ;
+        sample.env_attack = *EXPLOSION_ATTACK_DURATION;
+        sample.env_sustain = *EXPLOSION_SUSTAIN_DURATION;
+        sample.env_decay = *EXPLOSION_DECAY_DURATION;
+
+        self.play(sample, *EXPLOSION_VOLUME);
+    }
+
     /// Play a sample.
     pub fn play(&self, sample: Sample, volume: f32) {
         let mut new_generator = Generator::new(sample);
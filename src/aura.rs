@@ -0,0 +1,171 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+/// What an aura does to the allied units inside its radius.
+#[derive(Debug, Copy, Clone)]
+pub enum AuraEffect {
+    /// Health restored every second.
+    Heal(f64),
+    /// Multiplies the melee damage, only the strongest counts.
+    Damage(f64),
+    /// Multiplies the walking speed, only the strongest counts.
+    Speed(f64),
+    /// Morale gained every second.
+    Morale(f64),
+}
+
+/// Applies the effects to the allied units around it, including itself.
+#[derive(Component, Debug, Clone)]
+pub struct Aura {
+    pub radius: f64,
+    pub effects: Vec<AuraEffect>,
+}
+
+impl Aura {
+    pub fn new(radius: f64, effects: Vec<AuraEffect>) -> Self {
+        Aura { radius, effects }
+    }
+}
+
+/// The auras the different support units carry.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AuraKind {
+    /// Medics heal the units around them.
+    Healing,
+    /// Banner bearers make the units around them hit harder and walk faster.
+    Banner,
+    /// Drummers keep up the morale of the units around them.
+    Drums,
+}
+
+impl AuraKind {
+    /// The type of the unit carrying the aura.
+    pub fn unit_type(self) -> UnitType {
+        match self {
+            AuraKind::Healing => UnitType::Medic,
+            AuraKind::Banner => UnitType::BannerBearer,
+            AuraKind::Drums => UnitType::Drummer,
+        }
+    }
+
+    /// The aura with its radius and effects.
+    pub fn aura(self) -> Aura {
+        match self {
+            AuraKind::Healing => Aura::new(30.0, vec![AuraEffect::Heal(4.0)]),
+            AuraKind::Banner => {
+                Aura::new(40.0, vec![AuraEffect::Damage(1.25), AuraEffect::Speed(1.2)])
+            }
+            AuraKind::Drums => Aura::new(60.0, vec![AuraEffect::Morale(6.0)]),
+        }
+    }
+}
+
+/// The combined effects of all the auras a unit is in, recalculated every frame.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Buffs {
+    pub heal: f64,
+    pub damage: f64,
+    pub speed: f64,
+    pub morale: f64,
+}
+
+impl Default for Buffs {
+    fn default() -> Self {
+        Buffs {
+            heal: 0.0,
+            damage: 1.0,
+            speed: 1.0,
+            morale: 0.0,
+        }
+    }
+}
+
+impl Buffs {
+    fn apply(&mut self, effect: AuraEffect) {
+        match effect {
+            AuraEffect::Heal(amount) => self.heal += amount,
+            AuraEffect::Damage(factor) => self.damage = self.damage.max(factor),
+            AuraEffect::Speed(factor) => self.speed = self.speed.max(factor),
+            AuraEffect::Morale(amount) => self.morale += amount,
+        }
+    }
+}
+
+/// The damage multiplier of a unit that might not have any buffs.
+pub fn damage_factor(buffs: Option<&Buffs>) -> f64 {
+    buffs.map_or(1.0, |buffs| buffs.damage)
+}
+
+/// The walking speed of a unit with its charge, the speed auras and its stance applied.
+pub fn effective_speed(
+    walk: &Walk,
+    charge: Option<&Charge>,
+    buffs: Option<&Buffs>,
+    stance: Option<&Stance>,
+) -> f64 {
+    charge.map_or(walk.speed, |charge| charge.speed(walk))
+        * buffs.map_or(1.0, |buffs| buffs.speed)
+        * stance.map_or(1.0, |stance| stance.speed_factor())
+}
+
+#[derive(SystemData)]
+pub struct AuraSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    aura: ReadStorage<'a, Aura>,
+    pos: ReadStorage<'a, WorldPosition>,
+    max_health: ReadStorage<'a, MaxHealth>,
+    health: WriteStorage<'a, Health>,
+    buffs: WriteStorage<'a, Buffs>,
+}
+
+pub struct AuraSystem;
+impl<'a> System<'a> for AuraSystem {
+    type SystemData = AuraSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let factions = &system_data.factions;
+        let faction = &system_data.faction;
+
+        let auras: Vec<(Point, Option<&Faction>, &Aura)> =
+            (&*system_data.entities, &system_data.pos, &system_data.aura)
+                .join()
+                .map(|(e, pos, aura)| (pos.0, faction.get(e), aura))
+                .collect();
+
+        for (e, pos, buffs) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &mut system_data.buffs,
+        )
+            .join()
+        {
+            let unit_faction = faction.get(e);
+
+            *buffs = Buffs::default();
+            for (_, _, aura) in auras.iter().filter(|(aura_pos, aura_faction, aura)| {
+                factions.allied(unit_faction, *aura_faction)
+                    && pos.0.distance(**aura_pos) < aura.radius
+            }) {
+                for effect in aura.effects.iter() {
+                    buffs.apply(*effect);
+                }
+            }
+
+            // Heal the units up to the health they started with
+            if buffs.heal > 0.0 {
+                if let (Some(health), Some(max_health)) =
+                    (system_data.health.get_mut(e), system_data.max_health.get(e))
+                {
+                    health.0 = (health.0 + buffs.heal * dt).min(max_health.0);
+                }
+            }
+        }
+    }
+}
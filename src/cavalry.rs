@@ -0,0 +1,160 @@
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// How many pixels in front of the unit are checked for rough or rising ground
+const LOOKAHEAD: i32 = 8;
+// The most the ground in front can go up and down before it slows the charge
+const ROUGH_LIMIT: f64 = 2.0;
+// How much faster a charge is lost than it's built up on bad ground
+const DECELERATION_FACTOR: f64 = 4.0;
+// A unit only charges when it's this much faster than its walking speed
+const CHARGE_THRESHOLD: f64 = 1.5;
+// How much of the speed is kept after trampling a unit
+const TRAMPLE_SLOWDOWN: f64 = 0.8;
+// How much of the speed is transferred to the unit that's hit
+pub const CHARGE_KNOCKBACK: f64 = 0.5;
+
+/// Builds up speed while walking over open ground and hits hard when it runs into enemies.
+#[derive(Component, Debug, Clone)]
+pub struct Charge {
+    pub max_speed: f64,
+    /// The speed gained every second.
+    pub acceleration: f64,
+    /// The damage done for every pixel per second of speed.
+    pub damage: f64,
+
+    /// The speed built up during the current charge, the unit walks normally below its walking
+    /// speed.
+    speed: f64,
+    /// The units hit during the current charge, every unit is only hit once.
+    hit: Vec<Entity>,
+}
+
+impl Charge {
+    pub fn new(max_speed: f64, acceleration: f64, damage: f64) -> Self {
+        Charge {
+            max_speed,
+            acceleration,
+            damage,
+
+            speed: 0.0,
+            hit: Vec::new(),
+        }
+    }
+
+    /// How fast the unit is moving, the walking speed when it's not charging.
+    pub fn speed(&self, walk: &Walk) -> f64 {
+        self.speed.max(walk.speed)
+    }
+
+    /// Run into the unit, returns the damage done or `None` when not charging. Light units are
+    /// trampled and only slow the charge down, anything else stops it.
+    pub fn impact(&mut self, walk: &Walk, target: Entity, light: bool) -> Option<f64> {
+        if !self.is_charging(walk) {
+            return None;
+        }
+        if self.hit.contains(&target) {
+            return Some(0.0);
+        }
+        self.hit.push(target);
+
+        let damage = self.damage * self.speed;
+        if light {
+            self.speed *= TRAMPLE_SLOWDOWN;
+        } else {
+            self.speed = 0.0;
+        }
+
+        Some(damage)
+    }
+
+    /// Whether the unit is going fast enough to trample light units.
+    pub fn is_charging(&self, walk: &Walk) -> bool {
+        self.speed >= walk.speed * CHARGE_THRESHOLD
+    }
+
+    fn stop(&mut self) {
+        self.speed = 0.0;
+        self.hit.clear();
+    }
+}
+
+/// The height of the first solid pixel around the height in the column.
+fn surface_height(terrain: &Terrain, x: i32, y: i32) -> f64 {
+    let range = MAX_CLIMB_HEIGHT as i32;
+
+    (y - range..y + range)
+        .find(|y| terrain.is_solid((x, *y)))
+        .map_or(f64::from(y), f64::from)
+}
+
+/// How much the ground in front of the unit rises and how much it goes up and down besides that.
+fn ground_ahead(terrain: &Terrain, walk: &Walk, pos: Point, dir: f64) -> (f64, f64) {
+    let x = (pos.x + (walk.bounds.min.x + walk.bounds.max.x) / 2.0) as i32;
+    let y = (pos.y + walk.bounds.max.y) as i32;
+
+    let heights: Vec<f64> = (0..=LOOKAHEAD)
+        .map(|dist| surface_height(terrain, x + dist * dir as i32, y))
+        .collect();
+
+    // The y axis points down
+    let rise = heights[0] - heights[heights.len() - 1];
+    let change: f64 = heights
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .sum();
+
+    (rise, change - rise.abs())
+}
+
+#[derive(SystemData)]
+pub struct ChargeSystemData<'a> {
+    dt: Read<'a, DeltaTime>,
+    terrain: Read<'a, Terrain>,
+    dest: ReadStorage<'a, Destination>,
+    route: ReadStorage<'a, Route>,
+    pos: ReadStorage<'a, WorldPosition>,
+    state: ReadStorage<'a, UnitState>,
+    walk: ReadStorage<'a, Walk>,
+    charge: WriteStorage<'a, Charge>,
+}
+
+pub struct ChargeSystem;
+impl<'a> System<'a> for ChargeSystem {
+    type SystemData = ChargeSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let terrain = &system_data.terrain;
+
+        for (dest, route, pos, state, walk, charge) in (
+            &system_data.dest,
+            system_data.route.maybe(),
+            &system_data.pos,
+            &system_data.state,
+            &system_data.walk,
+            &mut system_data.charge,
+        )
+            .join()
+        {
+            let dir = walk_dir(route, dest, pos);
+            if *state != UnitState::Walk || dir == 0.0 {
+                // Every stop ends the charge
+                charge.stop();
+                continue;
+            }
+
+            let (rise, roughness) = ground_ahead(terrain, walk, pos.0, dir);
+            let speed = charge.speed(walk);
+            charge.speed = if rise > 0.0 || roughness > ROUGH_LIMIT {
+                // Uphill or uneven ground breaks the charge
+                let slowdown = charge.acceleration * DECELERATION_FACTOR * (1.0 + rise + roughness);
+                (speed - slowdown * dt).max(walk.speed)
+            } else {
+                (speed + charge.acceleration * dt).min(charge.max_speed)
+            };
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use collision::Discrete;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// Ledges up to this height are walked over without climbing
+pub const STEP_HEIGHT: f64 = 2.0;
+// The highest ledge a unit can climb without a ladder
+pub const MAX_CLIMB_HEIGHT: f64 = 8.0;
+// How much slower than walking a unit moves when climbing
+const CLIMB_SPEED_FACTOR: f64 = 0.3;
+
+const LADDER_WIDTH: usize = 6;
+
+/// An area in which units can climb walls of any height, the position is the top left.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Ladder {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Ladder {
+    fn bounds(self, pos: Point) -> BoundingBox {
+        BoundingBox::new(pos, Point::new(pos.x + self.width, pos.y + self.height))
+    }
+}
+
+/// A ladder that moves towards the destination until it stands against a wall.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct SiegeTower {
+    pub speed: f64,
+    pub dest: f64,
+}
+
+/// How high a unit needs to climb to walk one pixel further, `None` if the ledge is higher than
+/// the limit.
+pub fn ledge_height(
+    terrain: &Terrain,
+    walk: &Walk,
+    pos: Point,
+    dir: f64,
+    limit: f64,
+) -> Option<f64> {
+    let (x, y, width, height) = (walk.bounds + *Point::new(pos.x + dir, pos.y)).to_i32();
+
+    // Scan the rows in front of the unit once from its feet upwards, it fits where there are
+    // enough free rows on top of each other
+    let mut free_rows = 0;
+    for row in (y - limit as i32..y + height).rev() {
+        if (x..x + width).any(|column| terrain.is_solid((column, row))) {
+            free_rows = 0;
+            continue;
+        }
+
+        free_rows += 1;
+        if free_rows >= height {
+            return Some(f64::from(y - row));
+        }
+    }
+
+    None
+}
+
+pub fn place_ladder(world: &mut World, x: i32, height: f64) {
+    let ground = world.read_resource::<Terrain>().ground_height(x);
+    if let Some(y) = ground {
+        let pos = Point::new(f64::from(x), f64::from(y) - height);
+
+        world
+            .create_entity()
+            .with(Ladder {
+                width: LADDER_WIDTH as f64,
+                height,
+            })
+            .with(WorldPosition(pos))
+            .with(Rect::new(
+                pos.as_usize(),
+                (LADDER_WIDTH, height as usize),
+                WOOD_COLOR,
+                false,
+            ))
+            .build();
+    }
+}
+
+#[derive(SystemData)]
+pub struct ClimbSystemData<'a> {
+    terrain: Read<'a, Terrain>,
+    ladder: ReadStorage<'a, Ladder>,
+    dest: ReadStorage<'a, Destination>,
+    walk: ReadStorage<'a, Walk>,
+    route: ReadStorage<'a, Route>,
+    state: WriteStorage<'a, UnitState>,
+    vel: WriteStorage<'a, Velocity>,
+    pos: WriteStorage<'a, WorldPosition>,
+}
+
+pub struct ClimbSystem;
+impl<'a> System<'a> for ClimbSystem {
+    type SystemData = ClimbSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let terrain = &system_data.terrain;
+        let map_height = terrain.size().1 as f64;
+
+        // Collect the areas where the units can climb anything
+        let ladders: Vec<BoundingBox> = (&system_data.ladder, &system_data.pos)
+            .join()
+            .map(|(ladder, pos)| ladder.bounds(pos.0))
+            .collect();
+
+        for (dest, walk, route, state, vel, pos) in (
+            &system_data.dest,
+            &system_data.walk,
+            system_data.route.maybe(),
+            &mut system_data.state,
+            &mut system_data.vel,
+            &mut system_data.pos,
+        )
+            .join()
+        {
+            if *state != UnitState::Climb {
+                continue;
+            }
+
+            let dir = walk_dir(route, dest, pos);
+
+            let hit_box = walk.bounds + *pos.0;
+            let limit = if ladders.iter().any(|ladder| ladder.intersects(&*hit_box)) {
+                map_height
+            } else {
+                MAX_CLIMB_HEIGHT
+            };
+
+            match ledge_height(terrain, walk, pos.0, dir, limit) {
+                Some(lift) if lift < 1.0 => {
+                    // Step on the ledge
+                    pos.0.x += dir;
+                    *state = UnitState::Walk;
+                }
+                Some(_) => {
+                    // Keep climbing, the upwards speed is reset every frame so gravity doesn't
+                    // build up
+                    vel.y = -walk.speed * CLIMB_SPEED_FACTOR;
+                }
+                None => {
+                    // The wall is too high, wait in front of it until a ladder arrives
+                }
+            }
+        }
+    }
+}
+
+pub struct SiegeTowerSystem;
+impl<'a> System<'a> for SiegeTowerSystem {
+    type SystemData = (
+        Read<'a, DeltaTime>,
+        Read<'a, Terrain>,
+        ReadStorage<'a, SiegeTower>,
+        ReadStorage<'a, Ladder>,
+        WriteStorage<'a, WorldPosition>,
+        WriteStorage<'a, Rect>,
+    );
+
+    fn run(&mut self, (dt, terrain, tower, ladder, mut pos, mut rect): Self::SystemData) {
+        let dt = dt.to_seconds();
+
+        for (tower, ladder, pos, rect) in (&tower, &ladder, &mut pos, &mut rect).join() {
+            let dir = (tower.dest - pos.0.x).signum();
+            let bottom = pos.0.y + ladder.height;
+
+            // Stop when the ground in front is higher than a step, the tower is against a wall
+            let front = if dir < 0.0 {
+                pos.0.x - 1.0
+            } else {
+                pos.0.x + ladder.width
+            };
+            let blocked = match terrain.ground_height(front as i32) {
+                Some(ground) => f64::from(ground) < bottom - STEP_HEIGHT,
+                None => true,
+            };
+            if blocked || (tower.dest - pos.0.x).abs() < 1.0 {
+                continue;
+            }
+
+            pos.0.x += tower.speed * dt * dir;
+
+            // Follow the ground
+            if let Some(ground) = terrain.ground_height((pos.0.x + ladder.width / 2.0) as i32) {
+                pos.0.y = f64::from(ground) - ladder.height;
+            }
+
+            rect.pos = pos.0.as_usize();
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use specs::prelude::*;
+
+use super::*;
+
+// Where the allied units walk to when advancing, the enemy castle
+pub const ADVANCE_X: f64 = 1280.0;
+// Where the allied units walk to when retreating, in front of the castle
+pub const RETREAT_X: f64 = 10.0;
+
+/// The order the player gave to the allied units.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Order {
+    #[default]
+    Advance,
+    Hold,
+    Retreat,
+    /// Walk to the rally flag at the x position.
+    Rally(f64),
+}
+
+impl Order {
+    /// Where a unit at the x position walks to when following the order.
+    pub fn destination(self, x: f64) -> f64 {
+        match self {
+            Order::Advance => ADVANCE_X,
+            Order::Hold => x,
+            Order::Retreat => RETREAT_X,
+            Order::Rally(flag) => flag,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::Advance => "Advance",
+            Order::Hold => "Hold",
+            Order::Retreat => "Retreat",
+            Order::Rally(_) => "Rally",
+        }
+    }
+}
+
+/// Let the selected units follow the order, or all the allied units when none are selected.
+pub fn give_order(world: &mut World, order: Order) {
+    let selected = world.read_storage::<Selected>();
+    let any_selected = (&selected).join().next().is_some();
+    if !any_selected {
+        *world.write_resource::<Order>() = order;
+    }
+
+    let faction = world.read_storage::<Faction>();
+    let pos = world.read_storage::<WorldPosition>();
+    let mut dest = world.write_storage::<Destination>();
+    for (faction, pos, dest, is_selected) in (&faction, &pos, &mut dest, selected.maybe()).join() {
+        if *faction != Faction::PLAYER || (any_selected && is_selected.is_none()) {
+            continue;
+        }
+
+        dest.0 = order.destination(pos.0.x);
+    }
+}
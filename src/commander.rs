@@ -0,0 +1,198 @@
+use rand::{self, Rng};
+use specs::prelude::*;
+
+use super::*;
+
+/// How hard the enemy commander plays.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    /// The gold the commander receives every second.
+    fn income(self) -> f64 {
+        match self {
+            Difficulty::Easy => 2.0,
+            Difficulty::Normal => 3.0,
+            Difficulty::Hard => 5.0,
+        }
+    }
+
+    /// The seconds between each time the commander tries to buy a unit.
+    fn decision_delay(self) -> f64 {
+        match self {
+            Difficulty::Easy => 6.0,
+            Difficulty::Normal => 3.0,
+            Difficulty::Hard => 1.0,
+        }
+    }
+}
+
+/// The enemy AI that buys units with its own gold.
+#[derive(Debug)]
+pub struct Commander {
+    pub difficulty: Difficulty,
+    pub treasury: Treasury,
+    /// Where the bought units are created.
+    pub pos: Point,
+
+    decision_left: f64,
+    /// The unit the commander is saving gold for.
+    next: Option<UnitType>,
+}
+
+impl Default for Commander {
+    fn default() -> Self {
+        // Without income the commander never buys anything
+        Commander {
+            difficulty: Difficulty::default(),
+            treasury: Treasury::default(),
+            pos: Point::new(0.0, 0.0),
+
+            decision_left: 0.0,
+            next: None,
+        }
+    }
+}
+
+impl Commander {
+    pub fn new(difficulty: Difficulty, pos: Point) -> Self {
+        Commander {
+            difficulty,
+            treasury: Treasury::new(0.0, difficulty.income()),
+            pos,
+
+            decision_left: difficulty.decision_delay(),
+            next: None,
+        }
+    }
+
+    /// Decide which unit to buy, the counts are the amount of soldiers and archers.
+    fn choose(&self, player: (usize, usize), own: (usize, usize)) -> UnitType {
+        if self.difficulty == Difficulty::Easy {
+            // Don't look at the field at all
+            return if rand::thread_rng().gen() {
+                UnitType::Soldier
+            } else {
+                UnitType::Archer
+            };
+        }
+
+        // Field as many archers as the player fields melee soldiers, and the other way around
+        let ratio = |(soldiers, archers): (usize, usize), empty: f64| {
+            if soldiers + archers == 0 {
+                empty
+            } else {
+                soldiers as f64 / (soldiers + archers) as f64
+            }
+        };
+        let wanted_archers = ratio(player, 0.5);
+        let own_archers = 1.0 - ratio(own, 1.0);
+
+        if own_archers < wanted_archers {
+            UnitType::Archer
+        } else if player.1 > player.0 {
+            // Push through massed archers behind shields
+            UnitType::ShieldBearer
+        } else {
+            UnitType::Soldier
+        }
+    }
+}
+
+/// Count the amount of melee soldiers and archers, support units and siege engines are ignored.
+fn count_units<'a>(units: impl Iterator<Item = &'a UnitType>) -> (usize, usize) {
+    units.fold((0, 0), |(soldiers, archers), unit| match unit {
+        UnitType::Soldier | UnitType::ShieldBearer | UnitType::Cavalry => (soldiers + 1, archers),
+        UnitType::Archer => (soldiers, archers + 1),
+        UnitType::Medic | UnitType::BannerBearer | UnitType::Drummer | UnitType::BatteringRam => {
+            (soldiers, archers)
+        }
+    })
+}
+
+pub struct CommanderSystem;
+impl<'a> System<'a> for CommanderSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        ReadExpect<'a, Images>,
+        Read<'a, Factions>,
+        Write<'a, Commander>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, UnitType>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            dt,
+            images,
+            factions,
+            mut commander,
+            faction,
+            unit_type,
+            updater,
+        ): Self::SystemData,
+    ) {
+        let dt = dt.to_seconds();
+
+        commander.treasury.gold += commander.treasury.income * dt;
+
+        commander.decision_left -= dt;
+        if commander.decision_left > 0.0 {
+            return;
+        }
+        commander.decision_left = commander.difficulty.decision_delay();
+
+        let next = match commander.next {
+            Some(next) => next,
+            None => {
+                // Count the units of the player and the own units
+                let units_of = |side: Faction| {
+                    count_units(
+                        (&faction, &unit_type)
+                            .join()
+                            .filter(move |(faction, _)| **faction == side)
+                            .map(|(_, t)| t),
+                    )
+                };
+                let player = units_of(Faction::PLAYER);
+                let own = units_of(Faction::INVADERS);
+
+                commander.choose(player, own)
+            }
+        };
+
+        if !commander.treasury.spend(next.cost()) {
+            // Save gold for the unit
+            commander.next = Some(next);
+            return;
+        }
+        commander.next = None;
+
+        archetype(
+            updater.create_entity(&entities),
+            &images,
+            next,
+            factions.side(Faction::INVADERS),
+            commander.pos,
+        )
+        .build();
+    }
+}
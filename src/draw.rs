@@ -11,12 +11,29 @@ use crate::geom::*;
 use crate::terrain::*;
 
 const GREEN_BAR_COLOR: u32 = 0xFF_6A_BE_30;
+const HEALING_BAR_COLOR: u32 = 0xFF_99_E5_50;
 const RED_BAR_COLOR: u32 = 0xFF_AC_32_33;
+const SELECTION_COLOR: u32 = 0xFF_FB_F2_36;
+// A color that's not used in the images to find the transparent pixels after blitting
+const TRANSPARENT_COLOR: u32 = 0xFF_12_34_56;
+
+/// Blit the buffer on a transparent buffer of the same size to read back its pixels.
+fn raw_pixels(buf: &BlitBuffer) -> Vec<u32> {
+    let (width, height) = buf.size();
+    let mut pixels = vec![TRANSPARENT_COLOR; (width * height) as usize];
+    buf.blit(&mut pixels, width as usize, (0, 0));
+
+    pixels
+}
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct PixelParticle {
     pub color: u32,
     pub life: f64,
+    pub max_life: f64,
+    pub colors: (u32, u32),
+    pub gravity_scale: f64,
+    pub collides: bool,
 
     pub pos: Point2<usize>,
 }
@@ -26,9 +43,52 @@ impl PixelParticle {
         PixelParticle {
             color,
             life,
+            max_life: life,
+            colors: (color, color),
+            gravity_scale: 1.0,
+            collides: true,
             pos: Point2::new(0, 0),
         }
     }
+
+    /// Fade from the start color to this color during the life of the particle.
+    pub fn with_end_color(mut self, color: u32) -> Self {
+        self.colors.1 = color;
+
+        self
+    }
+
+    pub fn with_gravity_scale(mut self, gravity_scale: f64) -> Self {
+        self.gravity_scale = gravity_scale;
+
+        self
+    }
+
+    pub fn with_collision(mut self, collides: bool) -> Self {
+        self.collides = collides;
+
+        self
+    }
+
+    /// Set the start position, this is needed to calculate the collision of the first frame.
+    pub fn with_pos(mut self, pos: Point) -> Self {
+        self.pos = pos.as_usize();
+
+        self
+    }
+
+    /// Update the color from the life that's left.
+    pub fn update_color(&mut self) {
+        let ratio = 1.0 - (self.life / self.max_life).clamp(0.0, 1.0);
+
+        let channel = |shift: u32| {
+            let start = f64::from((self.colors.0 >> shift) & 0xFF);
+            let end = f64::from((self.colors.1 >> shift) & 0xFF);
+
+            ((start + (end - start) * ratio) as u32) << shift
+        };
+        self.color = channel(16) | channel(8) | channel(0);
+    }
 }
 
 #[derive(Component, Debug, Copy, Clone)]
@@ -94,6 +154,26 @@ impl Line {
     }
 }
 
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Rect {
+    pub pos: Point2<usize>,
+    pub size: (usize, usize),
+    pub color: u32,
+    /// Only the outline is drawn when the rectangle is not filled.
+    pub filled: bool,
+}
+
+impl Rect {
+    pub fn new(pos: Point2<usize>, size: (usize, usize), color: u32, filled: bool) -> Self {
+        Rect {
+            pos,
+            size,
+            color,
+            filled,
+        }
+    }
+}
+
 pub struct Images(pub HashMap<String, usize>);
 
 pub struct SpriteSystem;
@@ -161,6 +241,7 @@ impl Render {
         pos: Point2<usize>,
         health_ratio: f64,
         width: usize,
+        healing: bool,
     ) {
         if pos.x >= self.width || pos.y >= self.height {
             return;
@@ -175,9 +256,14 @@ impl Render {
         };
         let health = pos.x + (health_ratio * width as f64) as usize;
 
-        // Draw the green bar
+        // Draw the green bar, brighter while the unit is being healed
+        let color = if healing {
+            HEALING_BAR_COLOR
+        } else {
+            GREEN_BAR_COLOR
+        };
         for x in pos.x..health {
-            buffer[x + y] = GREEN_BAR_COLOR;
+            buffer[x + y] = color;
         }
 
         // Draw the red bar
@@ -221,6 +307,37 @@ impl Render {
         buffer[pos.x + pos.y * self.width] = color;
     }
 
+    pub fn draw_foreground_rect(&mut self, buffer: &mut [u32], rect: &Rect) {
+        let end_x = (rect.pos.x + rect.size.0).min(self.width);
+        let end_y = (rect.pos.y + rect.size.1).min(self.height);
+
+        for y in rect.pos.y..end_y {
+            for x in rect.pos.x..end_x {
+                let is_edge = x == rect.pos.x
+                    || y == rect.pos.y
+                    || x == rect.pos.x + rect.size.0 - 1
+                    || y == rect.pos.y + rect.size.1 - 1;
+                if rect.filled || is_edge {
+                    buffer[x + y * self.width] = rect.color;
+                }
+            }
+        }
+    }
+
+    /// Draw the outline of a selection between two corners in any order.
+    pub fn draw_selection(&mut self, buffer: &mut [u32], p1: Point, p2: Point) {
+        let min = Point::new(p1.x.min(p2.x).max(0.0), p1.y.min(p2.y).max(0.0));
+        let max = Point::new(p1.x.max(p2.x).max(0.0), p1.y.max(p2.y).max(0.0));
+
+        let rect = Rect::new(
+            min.as_usize(),
+            ((max.x - min.x) as usize + 1, (max.y - min.y) as usize + 1),
+            SELECTION_COLOR,
+            false,
+        );
+        self.draw_foreground_rect(buffer, &rect);
+    }
+
     pub fn draw_foreground_line(
         &mut self,
         buffer: &mut Vec<u32>,
@@ -247,14 +364,37 @@ impl Render {
         mask: &TerrainMask,
     ) -> Result<(), Box<dyn Error>> {
         let buf = &self.blit_buffers[mask.id].1;
+        let scale = mask.scale as i32;
 
         // Center the mask
         let mut pos = mask.pos;
-        pos.0 -= buf.size().0 / 2;
-        pos.1 -= buf.size().1 / 2;
+        pos.0 -= buf.size().0 * scale / 2;
+        pos.1 -= buf.size().1 * scale / 2;
 
         let size = self.size();
-        buf.blit(&mut terrain.buffer, size.0, pos);
+        if scale == 1 {
+            buf.blit(&mut terrain.buffer, size.0, pos);
+        } else {
+            // Draw every pixel of the mask as a square
+            let pixels = raw_pixels(buf);
+            for (index, color) in pixels.iter().enumerate() {
+                if *color == TRANSPARENT_COLOR {
+                    continue;
+                }
+
+                let x = pos.0 + index as i32 % buf.width() * scale;
+                let y = pos.1 + index as i32 / buf.width() * scale;
+                for py in (y..y + scale).filter(|py| *py >= 0 && *py < size.1 as i32) {
+                    for px in (x..x + scale).filter(|px| *px >= 0 && *px < size.0 as i32) {
+                        terrain.buffer[px as usize + py as usize * size.0] = *color;
+                    }
+                }
+            }
+        }
+        terrain.mark_changed(
+            pos.0.max(0) as usize,
+            (pos.0 + buf.size().0 * scale).max(0) as usize,
+        );
 
         Ok(())
     }
@@ -264,6 +404,7 @@ impl Render {
 
         let size = self.size();
         buf.blit(&mut terrain.buffer, size.0, (0, 0));
+        terrain.mark_changed(0, size.0);
     }
 
     pub fn draw_background_from_memory(&mut self, bytes: &[u8]) {
@@ -295,6 +436,28 @@ impl Render {
         self.blit_buffers.len() - 1
     }
 
+    /// Add a copy of the buffer with the colors of all pixels mixed halfway with the tint.
+    pub fn add_tinted_buf(&mut self, name: &str, source: usize, tint: u32) -> usize {
+        let buf = &self.blit_buffers[source].1;
+        let mut pixels = raw_pixels(buf);
+
+        let channel = |pixel: u32, shift: u32| {
+            ((((pixel >> shift) & 0xFF) + ((tint >> shift) & 0xFF)) / 2) << shift
+        };
+        for pixel in pixels
+            .iter_mut()
+            .filter(|pixel| **pixel != TRANSPARENT_COLOR)
+        {
+            *pixel = channel(*pixel, 16) | channel(*pixel, 8) | channel(*pixel, 0);
+        }
+
+        let buf = BlitBuffer::from_buffer(&pixels, buf.width(), TRANSPARENT_COLOR);
+
+        self.blit_buffers.push((String::from(name), buf));
+
+        self.blit_buffers.len() - 1
+    }
+
     pub fn add_anim_buf_from_memory(&mut self, name: &str, bytes: &[u8]) -> usize {
         let buf = AnimationBlitBuffer::from_memory(bytes).unwrap();
 
@@ -0,0 +1,37 @@
+use specs::prelude::*;
+
+use super::*;
+
+/// The gold of a player.
+#[derive(Debug, Default)]
+pub struct Treasury {
+    pub gold: f64,
+    /// The gold received every second.
+    pub income: f64,
+}
+
+impl Treasury {
+    pub fn new(gold: f64, income: f64) -> Self {
+        Treasury { gold, income }
+    }
+
+    /// Remove the cost from the gold if there is enough.
+    pub fn spend(&mut self, cost: f64) -> bool {
+        if self.gold < cost {
+            return false;
+        }
+
+        self.gold -= cost;
+
+        true
+    }
+}
+
+pub struct IncomeSystem;
+impl<'a> System<'a> for IncomeSystem {
+    type SystemData = (Read<'a, DeltaTime>, Write<'a, Treasury>);
+
+    fn run(&mut self, (dt, mut treasury): Self::SystemData) {
+        treasury.gold += treasury.income * dt.to_seconds();
+    }
+}
@@ -0,0 +1,191 @@
+use rand::{
+    self,
+    distributions::{Distribution, Uniform},
+};
+use specs::prelude::*;
+use specs_derive::Component;
+use std::f64::consts::PI;
+
+use super::*;
+
+pub const BLOOD: ParticleEffect = ParticleEffect {
+    rate: 0.0,
+    burst: 4,
+    duration: 0.0,
+    angle: -PI / 2.0,
+    spread: PI * 2.0,
+    speed: (5.0, 28.0),
+    life: (8.0, 10.0),
+    colors: (0xAC_32_33, 0x6E_1F_20),
+    gravity_scale: 1.0,
+    collides: true,
+};
+
+pub const SPARKS: ParticleEffect = ParticleEffect {
+    rate: 0.0,
+    burst: 5,
+    duration: 0.0,
+    angle: -PI / 2.0,
+    spread: PI,
+    speed: (15.0, 40.0),
+    life: (0.2, 0.5),
+    colors: (0xFB_F2_36, 0xDF_71_26),
+    gravity_scale: 0.5,
+    collides: false,
+};
+
+pub const DUST: ParticleEffect = ParticleEffect {
+    rate: 0.0,
+    burst: 6,
+    duration: 0.0,
+    angle: -PI / 2.0,
+    spread: PI,
+    speed: (5.0, 20.0),
+    life: (0.5, 1.0),
+    colors: (0x8F_7A_5E, 0xB9_A8_8C),
+    gravity_scale: 0.1,
+    collides: false,
+};
+
+pub const SMOKE: ParticleEffect = ParticleEffect {
+    rate: 30.0,
+    burst: 10,
+    duration: 0.5,
+    angle: -PI / 2.0,
+    spread: PI / 2.0,
+    speed: (10.0, 30.0),
+    life: (1.0, 2.5),
+    colors: (0x59_56_52, 0x9B_AD_B7),
+    gravity_scale: -0.05,
+    collides: false,
+};
+
+pub const DEBRIS: ParticleEffect = ParticleEffect {
+    rate: 0.0,
+    burst: 30,
+    duration: 0.0,
+    angle: -PI / 2.0,
+    spread: PI * 0.8,
+    speed: (20.0, 80.0),
+    life: (3.0, 5.0),
+    colors: (0x66_39_31, 0x45_28_3C),
+    gravity_scale: 1.0,
+    collides: true,
+};
+
+/// Describes the particles spawned by an emitter.
+#[derive(Debug, Copy, Clone)]
+pub struct ParticleEffect {
+    /// The amount of particles spawned per second while the emitter is alive.
+    pub rate: f64,
+    /// The amount of particles spawned at once when the emitter starts.
+    pub burst: usize,
+    /// How long the emitter stays alive in seconds.
+    pub duration: f64,
+
+    /// The direction in radians in which the particles are launched, `-PI / 2` is up.
+    pub angle: f64,
+    /// The width in radians of the cone around the angle.
+    pub spread: f64,
+    /// The minimum and maximum launch speed.
+    pub speed: (f64, f64),
+    /// The minimum and maximum life of a particle in seconds.
+    pub life: (f64, f64),
+    /// The color at the start and at the end of the life of a particle.
+    pub colors: (u32, u32),
+
+    /// How strongly gravity pulls the particles down, negative values make them rise.
+    pub gravity_scale: f64,
+    /// Whether the particles stick to the terrain when hitting it.
+    pub collides: bool,
+}
+
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Emitter {
+    pub effect: ParticleEffect,
+
+    time_alive: f64,
+    spawn_left: f64,
+    started: bool,
+}
+
+impl Emitter {
+    pub fn new(effect: ParticleEffect) -> Self {
+        Emitter {
+            effect,
+
+            time_alive: 0.0,
+            spawn_left: 0.0,
+            started: false,
+        }
+    }
+
+    /// Create a new entity emitting the effect at the position.
+    pub fn spawn(entities: &Entities, updater: &LazyUpdate, effect: ParticleEffect, pos: Point) {
+        let emitter = entities.create();
+        updater.insert(emitter, Emitter::new(effect));
+        updater.insert(emitter, WorldPosition(pos));
+    }
+}
+
+pub struct EmitterSystem;
+impl<'a> System<'a> for EmitterSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        Read<'a, LazyUpdate>,
+        ReadStorage<'a, WorldPosition>,
+        WriteStorage<'a, Emitter>,
+    );
+
+    fn run(&mut self, (entities, dt, updater, pos, mut emitter): Self::SystemData) {
+        let dt = dt.to_seconds();
+        let mut rng = rand::thread_rng();
+
+        for (entity, pos, emitter) in (&*entities, &pos, &mut emitter).join() {
+            let effect = emitter.effect;
+
+            let mut amount = 0;
+            if !emitter.started {
+                emitter.started = true;
+                amount += effect.burst;
+            }
+
+            emitter.spawn_left += effect.rate * dt;
+            amount += emitter.spawn_left as usize;
+            emitter.spawn_left = emitter.spawn_left.fract();
+
+            let angle = Uniform::new_inclusive(
+                effect.angle - effect.spread / 2.0,
+                effect.angle + effect.spread / 2.0,
+            );
+            let speed = Uniform::new_inclusive(effect.speed.0, effect.speed.1);
+            let life = Uniform::new_inclusive(effect.life.0, effect.life.1);
+
+            for _ in 0..amount {
+                let angle = angle.sample(&mut rng);
+                let speed = speed.sample(&mut rng);
+
+                let particle = entities.create();
+                updater.insert(
+                    particle,
+                    PixelParticle::new(effect.colors.0, life.sample(&mut rng))
+                        .with_end_color(effect.colors.1)
+                        .with_gravity_scale(effect.gravity_scale)
+                        .with_collision(effect.collides)
+                        .with_pos(pos.0),
+                );
+                updater.insert(particle, *pos);
+                updater.insert(
+                    particle,
+                    Velocity::new(angle.cos() * speed, angle.sin() * speed),
+                );
+            }
+
+            emitter.time_alive += dt;
+            if emitter.time_alive >= effect.duration {
+                let _ = entities.delete(entity);
+            }
+        }
+    }
+}
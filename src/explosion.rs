@@ -0,0 +1,213 @@
+use crate::audio::Audio;
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// How much the units are pushed upwards compared to sideways
+const KNOCKBACK_LIFT: f64 = 0.5;
+
+/// Makes the entity explode, the damage is taken from the `Damage` component.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Explosive {
+    pub radius: f64,
+    pub knockback: f64,
+}
+
+/// A placed explosive that goes off when a unit walks too close to it.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Mine {
+    pub trigger_radius: f64,
+}
+
+/// An explosion that will be resolved by the `ExplosionSystem`.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Explosion {
+    pub pos: Point,
+    pub radius: f64,
+    pub damage: Damage,
+    pub knockback: f64,
+    pub mask: Option<MaskId>,
+}
+
+impl Explosion {
+    pub fn new(pos: Point, explosive: &Explosive, damage: Damage, mask: Option<MaskId>) -> Self {
+        Explosion {
+            pos,
+            radius: explosive.radius,
+            damage,
+            knockback: explosive.knockback,
+            mask,
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct MineSystemData<'a> {
+    entities: Entities<'a>,
+    updater: Read<'a, LazyUpdate>,
+    mine: ReadStorage<'a, Mine>,
+    explosive: ReadStorage<'a, Explosive>,
+    dmg: ReadStorage<'a, Damage>,
+    mask: ReadStorage<'a, MaskId>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    pos: ReadStorage<'a, WorldPosition>,
+    bb: ReadStorage<'a, BoundingBox>,
+    walk: ReadStorage<'a, Walk>,
+}
+
+pub struct MineSystem;
+impl<'a> System<'a> for MineSystem {
+    type SystemData = MineSystemData<'a>;
+
+    fn run(&mut self, system_data: Self::SystemData) {
+        for (e, mine, explosive, dmg, mine_pos) in (
+            &*system_data.entities,
+            &system_data.mine,
+            &system_data.explosive,
+            &system_data.dmg,
+            &system_data.pos,
+        )
+            .join()
+        {
+            let faction = system_data.faction.get(e);
+
+            for (unit, unit_pos, unit_bb, _) in (
+                &*system_data.entities,
+                &system_data.pos,
+                &system_data.bb,
+                &system_data.walk,
+            )
+                .join()
+            {
+                // Only units hostile to the faction that placed the mine trigger it
+                if faction.is_some()
+                    && !system_data
+                        .factions
+                        .hostile(faction, system_data.faction.get(unit))
+                {
+                    continue;
+                }
+
+                // Check if the mine is inside the bounding box of the unit grown by the radius
+                let aabb = *unit_bb + *unit_pos.0;
+                let r = mine.trigger_radius;
+                if mine_pos.0.x < aabb.min.x - r
+                    || mine_pos.0.x > aabb.max.x + r
+                    || mine_pos.0.y < aabb.min.y - r
+                    || mine_pos.0.y > aabb.max.y + r
+                {
+                    continue;
+                }
+
+                system_data.updater.insert(
+                    system_data.entities.create(),
+                    Explosion::new(
+                        mine_pos.0,
+                        explosive,
+                        *dmg,
+                        system_data.mask.get(e).copied(),
+                    ),
+                );
+
+                let _ = system_data.entities.delete(e);
+                break;
+            }
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct ExplosionSystemData<'a> {
+    entities: Entities<'a>,
+    audio: Read<'a, Audio>,
+    updater: Read<'a, LazyUpdate>,
+    explosion: ReadStorage<'a, Explosion>,
+    pos: ReadStorage<'a, WorldPosition>,
+    bb: ReadStorage<'a, BoundingBox>,
+    vel: WriteStorage<'a, Velocity>,
+    armor: ReadStorage<'a, Armor>,
+    health: WriteStorage<'a, Health>,
+}
+
+pub struct ExplosionSystem;
+impl<'a> System<'a> for ExplosionSystem {
+    type SystemData = ExplosionSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        for (e, explosion) in (&*system_data.entities, &system_data.explosion).join() {
+            let _ = system_data.entities.delete(e);
+
+            if let Some(mask) = explosion.mask {
+                // Blast a crater in the terrain
+                system_data.updater.insert(
+                    system_data.entities.create(),
+                    TerrainMask::new(mask.id, explosion.pos.as_i32(), mask.size)
+                        .with_scale(explosion.damage.1.crater_scale()),
+                );
+            }
+
+            for (unit, unit_pos, unit_bb, unit_health) in (
+                &*system_data.entities,
+                &system_data.pos,
+                &system_data.bb,
+                &mut system_data.health,
+            )
+                .join()
+            {
+                let mut center = unit_pos.0;
+                center.x += unit_bb.width() / 2.0;
+                center.y += unit_bb.height() / 2.0;
+
+                let dist = explosion.pos.distance(*center);
+                if dist > explosion.radius {
+                    continue;
+                }
+
+                // The damage and the knockback fall off linearly towards the edge of the blast
+                let falloff = 1.0 - dist / explosion.radius;
+
+                if reduce_unit_health(
+                    &system_data.entities,
+                    unit,
+                    unit_health,
+                    system_data.armor.get(unit),
+                    Damage(explosion.damage.0 * falloff, explosion.damage.1),
+                ) {
+                    system_data.updater.insert(
+                        system_data.entities.create(),
+                        FloatingText {
+                            text: "x".to_string(),
+                            pos: unit_pos.0,
+                            time_alive: 2.0,
+                        },
+                    );
+                    continue;
+                }
+
+                let dir = (center.x - explosion.pos.x).signum();
+                if let Some(vel) = system_data.vel.get_mut(unit) {
+                    vel.x += dir * explosion.knockback * falloff;
+                    vel.y -= explosion.knockback * falloff * KNOCKBACK_LIFT;
+                }
+            }
+
+            // Throw up debris and smoke from just above the impact so it doesn't start inside the
+            // terrain
+            let start = Point::new(explosion.pos.x, explosion.pos.y - 2.0);
+            let burst = explosion.radius as usize;
+            Emitter::spawn(
+                &system_data.entities,
+                &system_data.updater,
+                ParticleEffect { burst, ..DEBRIS },
+                start,
+            );
+            Emitter::spawn(&system_data.entities, &system_data.updater, SMOKE, start);
+
+            // Play a sound
+            system_data.audio.play_explosion();
+        }
+    }
+}
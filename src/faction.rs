@@ -0,0 +1,176 @@
+use specs::prelude::*;
+use specs_derive::Component;
+use std::collections::HashMap;
+
+use super::*;
+
+// Where the bandits have their camp, between both castles
+pub const BANDIT_CAMP_X: f64 = 640.0;
+
+/// The side a unit, turret or projectile belongs to.
+#[derive(Component, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Faction(pub usize);
+
+impl Faction {
+    /// The faction controlled by the player.
+    pub const PLAYER: Faction = Faction(0);
+    /// The army attacking the castle of the player.
+    pub const INVADERS: Faction = Faction(1);
+    /// Raiders that attack everyone.
+    pub const BANDITS: Faction = Faction(2);
+}
+
+/// Where the units of a faction come from, where they go and what they look like.
+#[derive(Debug, Copy, Clone)]
+pub struct Side {
+    pub faction: Faction,
+    /// The x position of the base of the faction, its units flee there.
+    pub home: f64,
+    /// The x position the units of the faction march to.
+    pub target: f64,
+    /// The units hunt the closest hostile unit instead of marching to the target.
+    pub raids: bool,
+    /// The prefix of the names of the sprites of the units.
+    pub sprites: &'static str,
+    /// The direction the sprites of the units look in, `1.0` is to the right.
+    pub facing: f64,
+}
+
+/// How a faction treats another faction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Relation {
+    /// The units fight each other.
+    Hostile,
+    /// The units ignore each other and walk past each other.
+    Neutral,
+    /// The units fight side by side.
+    Allied,
+}
+
+/// The sides of all the factions and the relations between them, factions without a relation are
+/// neutral towards each other and every faction is allied with itself.
+#[derive(Debug)]
+pub struct Factions {
+    sides: HashMap<Faction, Side>,
+    relations: HashMap<(Faction, Faction), Relation>,
+}
+
+impl Default for Factions {
+    fn default() -> Self {
+        let mut factions = Factions {
+            sides: HashMap::new(),
+            relations: HashMap::new(),
+        };
+        factions.add(Side {
+            faction: Faction::PLAYER,
+            home: RETREAT_X,
+            target: ADVANCE_X,
+            raids: false,
+            sprites: "ally",
+            facing: 1.0,
+        });
+        factions.add(Side {
+            faction: Faction::INVADERS,
+            home: ADVANCE_X,
+            target: RETREAT_X,
+            raids: false,
+            sprites: "enemy",
+            facing: -1.0,
+        });
+        factions.add(Side {
+            faction: Faction::BANDITS,
+            home: BANDIT_CAMP_X,
+            target: BANDIT_CAMP_X,
+            raids: true,
+            // The bandits are tinted enemies and look the same way
+            sprites: "bandit",
+            facing: -1.0,
+        });
+
+        factions.set(Faction::PLAYER, Faction::INVADERS, Relation::Hostile);
+        factions.set(Faction::BANDITS, Faction::PLAYER, Relation::Hostile);
+        factions.set(Faction::BANDITS, Faction::INVADERS, Relation::Hostile);
+
+        factions
+    }
+}
+
+impl Factions {
+    pub fn add(&mut self, side: Side) {
+        self.sides.insert(side.faction, side);
+    }
+
+    /// The side of the faction, panics when the faction was never added.
+    pub fn side(&self, faction: Faction) -> Side {
+        self.sides[&faction]
+    }
+
+    /// Change the relation between both factions, relations always go both ways.
+    pub fn set(&mut self, a: Faction, b: Faction, relation: Relation) {
+        self.relations.insert((a, b), relation);
+        self.relations.insert((b, a), relation);
+    }
+
+    /// The relation between the factions of two entities, entities without a faction are neutral
+    /// towards everything.
+    pub fn relation(&self, a: Option<&Faction>, b: Option<&Faction>) -> Relation {
+        match (a, b) {
+            (Some(a), Some(b)) if a == b => Relation::Allied,
+            (Some(a), Some(b)) => self
+                .relations
+                .get(&(*a, *b))
+                .copied()
+                .unwrap_or(Relation::Neutral),
+            _ => Relation::Neutral,
+        }
+    }
+
+    pub fn hostile(&self, a: Option<&Faction>, b: Option<&Faction>) -> bool {
+        self.relation(a, b) == Relation::Hostile
+    }
+
+    pub fn allied(&self, a: Option<&Faction>, b: Option<&Faction>) -> bool {
+        self.relation(a, b) == Relation::Allied
+    }
+}
+
+#[derive(SystemData)]
+pub struct RaidSystemData<'a> {
+    entities: Entities<'a>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    pos: ReadStorage<'a, WorldPosition>,
+    health: ReadStorage<'a, Health>,
+    dest: WriteStorage<'a, Destination>,
+}
+
+/// Let the units of raiding factions go after the closest hostile unit.
+pub struct RaidSystem;
+impl<'a> System<'a> for RaidSystem {
+    type SystemData = RaidSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let entities = &system_data.entities;
+        let factions = &system_data.factions;
+        let faction = &system_data.faction;
+        let positions = &system_data.pos;
+        let health = &system_data.health;
+
+        for (e, unit_faction, pos, dest) in
+            (&**entities, faction, positions, &mut system_data.dest).join()
+        {
+            let side = factions.side(*unit_faction);
+            if !side.raids {
+                continue;
+            }
+
+            // Go back to the base when there is nobody left to attack
+            dest.0 = (&**entities, positions, health)
+                .join()
+                .filter(|(unit, _, _)| factions.hostile(faction.get(e), faction.get(*unit)))
+                .map(|(_, upos, _)| upos.0.x)
+                .min_by(|a, b| (a - pos.0.x).abs().total_cmp(&(b - pos.0.x).abs()))
+                .unwrap_or(side.home);
+        }
+    }
+}
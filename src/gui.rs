@@ -95,6 +95,16 @@ impl IngameGui {
         }
     }
 
+    /// Check if the position is on top of the menu bar.
+    pub fn contains(&self, pos: (i32, i32)) -> bool {
+        let size = self.menu_bg.size();
+
+        pos.0 >= self.bg_pos.0
+            && pos.0 < self.bg_pos.0 + size.0
+            && pos.1 >= self.bg_pos.1
+            && pos.1 < self.bg_pos.1 + size.1
+    }
+
     pub fn handle_mouse(&mut self, pos: (i32, i32), left_is_down: bool) {
         self.cs.mouse_pos = pos;
         self.cs.mouse_down = left_is_down;
@@ -129,6 +139,35 @@ impl IngameGui {
             .draw_label(buffer, default_font, &text.to_string(), pos);
     }
 
+    /// Show the selected units next to the menu bar with the commands they can be given.
+    pub fn draw_selection_panel(
+        &mut self,
+        buffer: &mut Vec<u32>,
+        (soldiers, archers, others): (usize, usize, usize),
+        stance: Option<Stance>,
+    ) {
+        let x = self.bg_pos.0 + self.menu_bg.size().0 + 8;
+        let y = self.bg_pos.1 + 4;
+
+        self.draw_label(
+            buffer,
+            &format!(
+                "Selected: {} soldiers, {} archers, {} others",
+                soldiers, archers, others
+            ),
+            (x, y),
+        );
+        let commands = match stance {
+            Some(stance) => {
+                self.draw_label(buffer, &format!("Stance: {}", stance.name()), (x, y + 10));
+
+                "Right click: move, A: attack, H: hold, T: stance"
+            }
+            None => "Right click: move, A: attack, H: hold",
+        };
+        self.draw_label(buffer, commands, (x, y + 20));
+    }
+
     pub fn render(&mut self, buffer: &mut Vec<u32>) {
         self.menu_bg.blit(buffer, self.size.0 as usize, self.bg_pos);
 
@@ -0,0 +1,54 @@
+use super::*;
+
+/// The state of the mouse in buffer coordinates, updated every frame.
+#[derive(Debug, Copy, Clone)]
+pub struct Mouse {
+    pub pos: Point,
+    pub left: bool,
+    pub right: bool,
+
+    prev_left: bool,
+    prev_right: bool,
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Mouse {
+            pos: Point::new(0.0, 0.0),
+            left: false,
+            right: false,
+
+            prev_left: false,
+            prev_right: false,
+        }
+    }
+}
+
+impl Mouse {
+    /// Update the buttons, the position is kept when the cursor is outside of the window.
+    pub fn update(&mut self, pos: Option<(i32, i32)>, left: bool, right: bool) {
+        if let Some(pos) = pos {
+            self.pos = Point::new(f64::from(pos.0), f64::from(pos.1));
+        }
+
+        self.prev_left = self.left;
+        self.left = left;
+        self.prev_right = self.right;
+        self.right = right;
+    }
+
+    /// The left button went down this frame.
+    pub fn left_pressed(&self) -> bool {
+        self.left && !self.prev_left
+    }
+
+    /// The left button went up this frame.
+    pub fn left_released(&self) -> bool {
+        !self.left && self.prev_left
+    }
+
+    /// The right button went down this frame.
+    pub fn right_pressed(&self) -> bool {
+        self.right && !self.prev_right
+    }
+}
@@ -4,120 +4,395 @@ use specs::*;
 
 use crate::*;
 
-const WOOD_COLOR: u32 = 0x66_39_31;
+pub const WOOD_COLOR: u32 = 0x66_39_31;
 
-pub fn buy_archer(world: &mut World) {
-    let archer_sprite = {
-        let images = &*world.read_resource::<Images>();
-
-        *images.0.get("ally-archer1").unwrap()
-    };
+const BANNER_HEIGHT: usize = 14;
 
+/// Add the components of an archer to the entity, works for both `World::create_entity` and
+/// `LazyUpdate::create_entity`.
+pub fn archer<B: Builder>(builder: B, images: &Images, side: Side, pos: Point) -> B {
     let health = 20.0;
 
-    world
-        .create_entity()
-        .with(Ally)
-        .with(Anim::new(archer_sprite, Animation::start(0, 2, true)))
-        .with(WorldPosition(Point::new(1.0, 340.0)))
+    let builder = builder
+        .with(UnitType::Archer)
+        .with(WorldPosition(pos))
         .with(Walk::new(
             BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
             20.0,
         ))
-        .with(BoundingBox::new(
-            Point::new(0.0, 0.0),
-            Point::new(5.0, 10.0),
-        ))
-        .with(Destination(1280.0))
-        .with(Health(20.0))
+        .with(Health(health))
+        .with(MaxHealth(health))
         .with(HealthBar {
             health,
             max_health: health,
             width: 5,
             pos: Point2::new(0, 0),
             offset: (1, -3),
+            healing: false,
         })
-        .with(Melee::new(5.0, 1.0))
-        .with(Turret {
-            delay: 3.0,
-            min_distance: 20.0,
-            max_strength: 150.0,
-            flight_time: 2.0,
-            strength_variation: 0.1,
-            ..Turret::default()
-        })
+        .with(Melee::new(Damage(5.0, DamageType::Slash), 1.0))
         .with(TurretOffset((2.0, 2.0)))
+        .with(Ranged::new(150.0, 25.0))
         .with(Point::new(0.0, 0.0))
         .with(Arrow(3.0))
         .with(Line::new(WOOD_COLOR))
-        .with(Damage(5.0))
+        .with(Damage(5.0, DamageType::Pierce))
+        .with(Armor {
+            siege: 0.6,
+            ..Armor::default()
+        })
         .with(ProjectileBoundingBox(BoundingBox::new(
             Point::new(0.0, 0.0),
             Point::new(1.0, 1.0),
         )))
-        .with(IgnoreCollision::Ally)
-        .with(UnitState::Walk)
-        .build();
-}
+        .with(Velocity::new(0.0, 0.0))
+        .with(Route::default())
+        .with(Stance::Skirmish)
+        .with(Morale::new(100.0, side.home))
+        .with(Buffs::default())
+        .with(UnitState::Walk);
 
-pub fn buy_soldier(world: &mut World) {
-    let soldier_sprite = {
-        let images = &*world.read_resource::<Images>();
-
-        *images.0.get("ally-melee1").unwrap()
+    let turret = Turret {
+        delay: 3.0,
+        min_distance: 20.0,
+        strength: 150.0,
+        trajectory: Trajectory::Low,
+        accuracy: 0.9,
+        ..Turret::default()
     };
 
-    let health = 50.0;
+    let builder = builder.with(side.faction).with(Destination(side.target));
 
-    world
-        .create_entity()
-        .with(Ally)
-        .with(Sprite::new(soldier_sprite))
-        .with(WorldPosition(Point::new(1.0, 340.0)))
-        .with(Walk::new(
-            BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
-            15.0,
-        ))
-        .with(BoundingBox::new(
-            Point::new(0.0, 0.0),
-            Point::new(5.0, 10.0),
-        ))
-        .with(Destination(1280.0))
+    // The units of the player face the other way than the rest
+    if side.faction == Faction::PLAYER {
+        builder
+            .with(Anim::new(
+                images.0[&format!("{}-archer1", side.sprites)],
+                Animation::start(0, 2, true),
+            ))
+            .with(BoundingBox::new(
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 10.0),
+            ))
+            .with(Turret {
+                targeting: Targeting::Prefer(UnitType::Soldier),
+                ..turret
+            })
+    } else {
+        builder
+            .with(Sprite::new(images.0[&format!("{}-archer1", side.sprites)]))
+            .with(BoundingBox::new(
+                Point::new(1.0, 0.0),
+                Point::new(5.0, 10.0),
+            ))
+            .with(Turret {
+                targeting: Targeting::Furthest,
+                ..turret
+            })
+    }
+}
+
+/// The name of the sprite of a melee unit without the prefix of its faction.
+fn melee_sprite(unit_type: UnitType) -> &'static str {
+    match unit_type {
+        UnitType::ShieldBearer => "shield1",
+        UnitType::Cavalry => "cavalry1",
+        UnitType::Medic | UnitType::BannerBearer | UnitType::Drummer => "support1",
+        UnitType::Soldier | UnitType::Archer | UnitType::BatteringRam => "melee1",
+    }
+}
+
+/// Add the components all the units fighting in melee share to the entity.
+fn melee_unit<B: Builder>(
+    builder: B,
+    images: &Images,
+    side: Side,
+    pos: Point,
+    unit_type: UnitType,
+    health: f64,
+    melee: Melee,
+) -> B {
+    let builder = builder
+        .with(unit_type)
+        .with(WorldPosition(pos))
         .with(Health(health))
+        .with(MaxHealth(health))
         .with(HealthBar {
             health,
             max_health: health,
             width: 10,
             pos: Point2::new(0, 0),
             offset: (-2, -3),
+            healing: false,
+        })
+        .with(melee)
+        .with(Velocity::new(0.0, 0.0))
+        .with(Route::default())
+        .with(Morale::new(100.0, side.home))
+        .with(Buffs::default())
+        .with(UnitState::Walk);
+
+    let builder = builder
+        .with(side.faction)
+        .with(Destination(side.target))
+        .with(Sprite::new(
+            images.0[&format!("{}-{}", side.sprites, melee_sprite(unit_type))],
+        ));
+
+    if side.faction == Faction::PLAYER {
+        builder
+            .with(Walk::new(
+                BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
+                15.0,
+            ))
+            .with(BoundingBox::new(
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 10.0),
+            ))
+    } else {
+        builder
+            .with(Walk::new(
+                BoundingBox::new(Point::new(2.0, 5.0), Point::new(5.0, 10.0)),
+                15.0,
+            ))
+            .with(BoundingBox::new(
+                Point::new(1.0, 0.0),
+                Point::new(6.0, 10.0),
+            ))
+    }
+}
+
+/// Add the components of a melee soldier to the entity.
+pub fn soldier<B: Builder>(builder: B, images: &Images, side: Side, pos: Point) -> B {
+    melee_unit(
+        builder,
+        images,
+        side,
+        pos,
+        UnitType::Soldier,
+        50.0,
+        Melee::new(Damage(10.0, DamageType::Slash), 1.0),
+    )
+    .with(Armor {
+        pierce: 0.2,
+        slash: 0.2,
+        siege: 0.6,
+        ..Armor::default()
+    })
+}
+
+/// Add the components of a melee soldier carrying a shield to the entity.
+pub fn shield_bearer<B: Builder>(builder: B, images: &Images, side: Side, pos: Point) -> B {
+    melee_unit(
+        builder,
+        images,
+        side,
+        pos,
+        UnitType::ShieldBearer,
+        60.0,
+        Melee::new(Damage(6.0, DamageType::Slash), 1.0),
+    )
+    .with(Armor {
+        pierce: 0.4,
+        slash: 0.4,
+        blunt: 0.1,
+        siege: 0.5,
+        ..Armor::default()
+    })
+    .with(Shield::new(0.6, 0.95, side.facing))
+    .with(Stance::Aggressive)
+}
+
+/// Add the components of a mounted unit that charges into the enemy to the entity.
+pub fn cavalry<B: Builder>(builder: B, images: &Images, side: Side, pos: Point) -> B {
+    melee_unit(
+        builder,
+        images,
+        side,
+        pos,
+        UnitType::Cavalry,
+        70.0,
+        Melee::new(Damage(8.0, DamageType::Pierce), 1.0),
+    )
+    // Armored knights shrug off arrows
+    .with(Armor {
+        pierce: 0.7,
+        slash: 0.5,
+        blunt: 0.2,
+        siege: 0.5,
+        ..Armor::default()
+    })
+    .with(Charge::new(45.0, 10.0, 0.5))
+}
+
+/// Add the components of a unit that helps the units around it to the entity.
+pub fn support<B: Builder>(
+    builder: B,
+    images: &Images,
+    kind: AuraKind,
+    side: Side,
+    pos: Point,
+) -> B {
+    // Support units look like soldiers but barely fight
+    melee_unit(
+        builder,
+        images,
+        side,
+        pos,
+        kind.unit_type(),
+        30.0,
+        Melee::new(Damage(2.0, DamageType::Blunt), 1.0),
+    )
+    .with(Armor {
+        siege: 0.6,
+        ..Armor::default()
+    })
+    .with(kind.aura())
+}
+
+/// Add the components of a battering ram to the entity.
+pub fn battering_ram<B: Builder>(builder: B, images: &Images, side: Side, pos: Point) -> B {
+    let health = 300.0;
+    let size = (16, 8);
+    let bounds = BoundingBox::new(
+        Point::new(0.0, 0.0),
+        Point::new(size.0 as f64, size.1 as f64),
+    );
+
+    builder
+        .with(UnitType::BatteringRam)
+        .with(WorldPosition(pos))
+        .with(Walk::new(bounds, 4.0))
+        .with(bounds)
+        .with(Health(health))
+        .with(MaxHealth(health))
+        .with(HealthBar {
+            health,
+            max_health: health,
+            width: size.0,
+            pos: Point2::new(0, 0),
+            offset: (0, -3),
+            healing: false,
+        })
+        .with(Velocity::new(0.0, 0.0))
+        .with(Ram::new(
+            2,
+            1.5,
+            MaskId {
+                id: images.0["bighole1"],
+                size: (5, 5),
+            },
+        ))
+        .with(Rect::new(pos.as_usize(), size, WOOD_COLOR, true))
+        // The wooden frame stops arrows and blades but burns and breaks under stones
+        .with(Armor {
+            pierce: 0.9,
+            slash: 0.8,
+            blunt: 0.3,
+            fire: -0.5,
+            siege: 0.0,
         })
-        .with(Melee::new(10.0, 1.0))
         .with(UnitState::Walk)
-        .build();
+        .with(side.faction)
+        .with(Destination(side.target))
+}
+
+/// Add the components of a unit of the type to the entity.
+pub fn archetype<B: Builder>(
+    builder: B,
+    images: &Images,
+    unit_type: UnitType,
+    side: Side,
+    pos: Point,
+) -> B {
+    match unit_type {
+        UnitType::Soldier => soldier(builder, images, side, pos),
+        UnitType::Archer => archer(builder, images, side, pos),
+        UnitType::ShieldBearer => shield_bearer(builder, images, side, pos),
+        UnitType::BatteringRam => battering_ram(builder, images, side, pos),
+        UnitType::Cavalry => cavalry(builder, images, side, pos),
+        UnitType::Medic => support(builder, images, AuraKind::Healing, side, pos),
+        UnitType::BannerBearer => support(builder, images, AuraKind::Banner, side, pos),
+        UnitType::Drummer => support(builder, images, AuraKind::Drums, side, pos),
+    }
+}
+
+/// Create a unit for the player at the castle.
+fn buy_unit(world: &mut World, unit_type: UnitType) {
+    let entities = world.entities();
+    let updater = world.read_resource::<LazyUpdate>();
+    let images = world.read_resource::<Images>();
+    let order = *world.read_resource::<Order>();
+
+    let pos = Point::new(1.0, 340.0);
+    // Follow the last order of the player
+    let side = Side {
+        target: order.destination(pos.x),
+        ..world.read_resource::<Factions>().side(Faction::PLAYER)
+    };
+    archetype(
+        updater.create_entity(&entities),
+        &images,
+        unit_type,
+        side,
+        pos,
+    )
+    .build();
+}
+
+pub fn buy_archer(world: &mut World) {
+    buy_unit(world, UnitType::Archer);
+}
+
+pub fn buy_soldier(world: &mut World) {
+    buy_unit(world, UnitType::Soldier);
+}
+
+pub fn buy_shield_bearer(world: &mut World) {
+    buy_unit(world, UnitType::ShieldBearer);
+}
+
+pub fn buy_battering_ram(world: &mut World) {
+    buy_unit(world, UnitType::BatteringRam);
+}
+
+pub fn buy_cavalry(world: &mut World) {
+    buy_unit(world, UnitType::Cavalry);
+}
+
+pub fn buy_medic(world: &mut World) {
+    buy_unit(world, UnitType::Medic);
+}
+
+pub fn buy_banner_bearer(world: &mut World) {
+    buy_unit(world, UnitType::BannerBearer);
+}
+
+pub fn buy_drummer(world: &mut World) {
+    buy_unit(world, UnitType::Drummer);
 }
 
-pub fn place_turrets(world: &mut World, level: u8) {
-    let (projectile1, bighole1, enemy_soldier1, enemy_archer1) = {
+pub fn place_turrets(world: &mut World, level: u8, difficulty: Difficulty) {
+    let (projectile1, bighole1) = {
         let images = &*world.read_resource::<Images>();
 
         (
             *images.0.get("projectile1").unwrap(),
             *images.0.get("bighole1").unwrap(),
-            *images.0.get("enemy-melee1").unwrap(),
-            *images.0.get("enemy-archer1").unwrap(),
         )
     };
 
     if level == 1 {
         world
             .create_entity()
-            .with(Enemy)
+            .with(Faction::INVADERS)
             .with(Turret {
                 delay: 3.0,
                 min_distance: 50.0,
-                max_strength: 310.0,
-                flight_time: 5.0,
-                strength_variation: 0.05,
+                strength: 310.0,
+                trajectory: Trajectory::High,
+                accuracy: 0.95,
+                targeting: Targeting::First,
                 ..Turret::default()
             })
             .with(Point::new(1270.0, 295.0))
@@ -126,22 +401,53 @@ pub fn place_turrets(world: &mut World, level: u8) {
                 id: bighole1,
                 size: (5, 5),
             })
+            .with(Bounce::new(0.3, 0.5, 8.0))
             .with(ProjectileBoundingBox(BoundingBox::new(
                 Point::new(0.0, 0.0),
                 Point::new(5.0, 5.0),
             )))
-            .with(Damage(30.0))
+            .with(Damage(30.0, DamageType::Siege))
             .build();
 
         world
             .create_entity()
-            .with(Enemy)
+            .with(Faction::INVADERS)
+            .with(Turret {
+                delay: 6.0,
+                min_distance: 80.0,
+                strength: 300.0,
+                trajectory: Trajectory::High,
+                accuracy: 0.95,
+                targeting: Targeting::Strongest,
+                ..Turret::default()
+            })
+            .with(Point::new(1262.0, 305.0))
+            .with(ProjectileSprite(Sprite::new(projectile1)))
+            .with(MaskId {
+                id: bighole1,
+                size: (5, 5),
+            })
+            .with(Explosive {
+                radius: 20.0,
+                knockback: 80.0,
+            })
+            .with(ProjectileBoundingBox(BoundingBox::new(
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 5.0),
+            )))
+            .with(Damage(25.0, DamageType::Fire))
+            .build();
+
+        world
+            .create_entity()
+            .with(Faction::INVADERS)
             .with(Turret {
                 delay: 1.0,
                 min_distance: 50.0,
-                max_strength: 290.0,
-                flight_time: 4.0,
-                strength_variation: 0.05,
+                strength: 290.0,
+                trajectory: Trajectory::Low,
+                accuracy: 0.95,
+                targeting: Targeting::Weakest,
                 ..Turret::default()
             })
             .with(Point::new(1255.0, 315.0))
@@ -151,85 +457,175 @@ pub fn place_turrets(world: &mut World, level: u8) {
                 Point::new(0.0, 0.0),
                 Point::new(1.0, 1.0),
             )))
-            .with(Damage(10.0))
+            .with(Damage(10.0, DamageType::Pierce))
             .build();
 
-        for i in 0..5 {
-            let health = 50.0;
-
+        // The siege engine of the player
+        let ground = world.read_resource::<Terrain>().ground_height(30);
+        if let Some(y) = ground {
             world
                 .create_entity()
-                .with(Enemy)
-                .with(Sprite::new(enemy_soldier1))
-                .with(WorldPosition(Point::new(1130.0 - 20.0 * i as f64, 320.0)))
-                .with(Walk::new(
-                    BoundingBox::new(Point::new(2.0, 5.0), Point::new(5.0, 10.0)),
-                    15.0,
-                ))
-                .with(BoundingBox::new(
-                    Point::new(1.0, 0.0),
-                    Point::new(6.0, 10.0),
-                ))
-                .with(Destination(10.0))
-                .with(Health(health))
-                .with(HealthBar {
-                    health,
-                    max_health: health,
-                    width: 10,
-                    pos: Point2::new(0, 0),
-                    offset: (-2, -3),
+                .with(Faction::PLAYER)
+                .with(PlayerAim::new(10.0, 3.0, 300.0, 4.0))
+                .with(WorldPosition(Point::new(30.0, f64::from(y) - 6.0)))
+                .with(Sprite::new(projectile1))
+                .with(ProjectileSprite(Sprite::new(projectile1)))
+                .with(MaskId {
+                    id: bighole1,
+                    size: (5, 5),
                 })
-                .with(Melee::new(10.0, 1.0))
-                .with(UnitState::Walk)
+                .with(ProjectileBoundingBox(BoundingBox::new(
+                    Point::new(0.0, 0.0),
+                    Point::new(5.0, 5.0),
+                )))
+                .with(Damage(30.0, DamageType::Siege))
                 .build();
         }
 
-        for i in 0..20 {
-            let health = 20.0;
+        // Places where the player can build towers
+        for x in &[80, 140, 200] {
+            place_tower_slot(world, *x);
+        }
+
+        // A ladder against the wall of the enemy castle
+        place_ladder(world, 1225, 60.0);
+
+        // The enemy pushes a siege tower towards the castle of the player
+        let ground = world.read_resource::<Terrain>().ground_height(1100);
+        if let Some(y) = ground {
+            let (width, height) = (10, 40);
+            let pos = Point::new(1100.0, f64::from(y - height));
 
             world
                 .create_entity()
-                .with(Enemy)
-                .with(Sprite::new(enemy_archer1))
-                .with(WorldPosition(Point::new(1140.0 - 20.0 * i as f64, 320.0)))
-                .with(Walk::new(
-                    BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
-                    20.0,
-                ))
-                .with(BoundingBox::new(
-                    Point::new(1.0, 0.0),
-                    Point::new(5.0, 10.0),
-                ))
-                .with(Destination(10.0))
-                .with(Health(health))
-                .with(HealthBar {
-                    health,
-                    max_health: health,
-                    width: 5,
-                    pos: Point2::new(0, 0),
-                    offset: (1, -3),
+                .with(SiegeTower {
+                    speed: 4.0,
+                    dest: 10.0,
                 })
-                .with(Melee::new(5.0, 1.0))
-                .with(Turret {
-                    delay: 3.0,
-                    min_distance: 20.0,
-                    max_strength: 150.0,
-                    flight_time: 2.0,
-                    strength_variation: 0.1,
-                    ..Turret::default()
+                .with(Ladder {
+                    width: f64::from(width),
+                    height: f64::from(height),
                 })
-                .with(TurretOffset((2.0, 2.0)))
-                .with(Point::new(0.0, 0.0))
-                .with(Arrow(3.0))
-                .with(Line::new(WOOD_COLOR))
-                .with(Damage(5.0))
-                .with(ProjectileBoundingBox(BoundingBox::new(
-                    Point::new(0.0, 0.0),
-                    Point::new(1.0, 1.0),
-                )))
-                .with(IgnoreCollision::Enemy)
-                .with(UnitState::Walk)
+                .with(WorldPosition(pos))
+                .with(Rect::new(
+                    pos.as_usize(),
+                    (width as usize, height as usize),
+                    WOOD_COLOR,
+                    true,
+                ))
                 .build();
         }
+
+        // Banners in front of both castles encourage the units around them
+        for (x, faction) in &[(40, Faction::PLAYER), (1200, Faction::INVADERS)] {
+            let ground = world.read_resource::<Terrain>().ground_height(*x);
+            if let Some(y) = ground {
+                let pos = Point::new(f64::from(*x), f64::from(y - BANNER_HEIGHT as i32));
+
+                world
+                    .create_entity()
+                    .with(*faction)
+                    .with(Aura::new(60.0, vec![AuraEffect::Morale(5.0)]))
+                    .with(WorldPosition(pos))
+                    .with(Rect::new(
+                        pos.as_usize(),
+                        (1, BANNER_HEIGHT),
+                        WOOD_COLOR,
+                        true,
+                    ))
+                    .build();
+            }
+        }
+
+        // Hidden mines in front of the castle
+        for x in &[1000, 1040, 1080] {
+            let ground = world.read_resource::<Terrain>().ground_height(*x);
+            if let Some(y) = ground {
+                world
+                    .create_entity()
+                    .with(Mine {
+                        trigger_radius: 2.0,
+                    })
+                    .with(Explosive {
+                        radius: 16.0,
+                        knockback: 60.0,
+                    })
+                    .with(WorldPosition(Point::new(f64::from(*x), f64::from(y))))
+                    .with(MaskId {
+                        id: bighole1,
+                        size: (5, 5),
+                    })
+                    .with(Damage(30.0, DamageType::Fire))
+                    .with(Faction::INVADERS)
+                    .build();
+            }
+        }
+
+        // The enemy attacks in waves coming from the castle
+        let spawn = Point::new(1140.0, 320.0);
+        // Bandits come out of their camp in the middle and attack both sides
+        let camp = world
+            .read_resource::<Terrain>()
+            .ground_height(BANDIT_CAMP_X as i32)
+            .map_or(spawn, |y| Point::new(BANDIT_CAMP_X, f64::from(y - 12)));
+        world.insert(WaveSpawner::new(vec![
+            Wave {
+                start: WaveStart::At(5.0),
+                faction: Faction::INVADERS,
+                units: vec![(UnitType::Soldier, 5), (UnitType::Archer, 10)],
+                pos: spawn,
+                interval: 0.8,
+            },
+            Wave {
+                start: WaveStart::At(40.0),
+                faction: Faction::BANDITS,
+                units: vec![(UnitType::Soldier, 6), (UnitType::Archer, 4)],
+                pos: camp,
+                interval: 1.0,
+            },
+            Wave {
+                start: WaveStart::At(60.0),
+                faction: Faction::INVADERS,
+                units: vec![
+                    (UnitType::ShieldBearer, 3),
+                    (UnitType::Archer, 10),
+                    (UnitType::Soldier, 5),
+                    (UnitType::Cavalry, 3),
+                ],
+                pos: spawn,
+                interval: 0.6,
+            },
+            Wave {
+                start: WaveStart::Cleared(20.0),
+                faction: Faction::INVADERS,
+                units: vec![
+                    (UnitType::Drummer, 1),
+                    (UnitType::BatteringRam, 1),
+                    (UnitType::Soldier, 15),
+                    (UnitType::Medic, 2),
+                    (UnitType::Archer, 15),
+                ],
+                pos: spawn,
+                interval: 0.5,
+            },
+            Wave {
+                start: WaveStart::Cleared(30.0),
+                faction: Faction::INVADERS,
+                units: vec![
+                    (UnitType::BannerBearer, 1),
+                    (UnitType::ShieldBearer, 10),
+                    (UnitType::BatteringRam, 2),
+                    (UnitType::Soldier, 25),
+                    (UnitType::Medic, 3),
+                    (UnitType::Archer, 25),
+                    (UnitType::Drummer, 1),
+                ],
+                pos: spawn,
+                interval: 0.4,
+            },
+        ]));
+
+        // Between the waves the enemy buys extra units
+        world.insert(Commander::new(difficulty, spawn));
     }
 }
@@ -1,14 +1,33 @@
 mod ai;
+mod armor;
 mod audio;
+mod aura;
+mod cavalry;
+mod climb;
+mod command;
+mod commander;
 mod draw;
+mod economy;
+mod emitter;
+mod explosion;
+mod faction;
 mod geom;
 mod gui;
+mod input;
 mod level;
+mod morale;
+mod nav;
 mod physics;
 mod projectile;
+mod ram;
+mod select;
+mod shield;
+mod siege;
 mod terrain;
+mod tower;
 mod turret;
 mod unit;
+mod wave;
 
 use minifb::*;
 use rust_embed::RustEmbed;
@@ -20,22 +39,49 @@ use std::{
 };
 
 use ai::*;
+use armor::*;
 use audio::Audio;
+use aura::*;
+use cavalry::*;
+use climb::*;
+use command::*;
+use commander::*;
 use draw::*;
+use economy::*;
+use emitter::*;
+use explosion::*;
+use faction::*;
 use geom::*;
 use gui::*;
+use input::*;
 use level::*;
+use morale::*;
+use nav::*;
 use physics::*;
 use projectile::*;
+use ram::*;
+use select::*;
+use shield::*;
+use siege::*;
 use terrain::*;
+use tower::*;
 use turret::*;
 use unit::*;
+use wave::*;
 
 const WIDTH: usize = 1280;
 const HEIGHT: usize = 540;
 
 const GRAVITY: f64 = 98.1;
 
+const AIM_PREVIEW_COLOR: u32 = 0xFF_FF_FF_FF;
+
+const RALLY_FLAG_COLOR: u32 = 0xFF_3F_7F_D8;
+const RALLY_FLAG_HEIGHT: f64 = 12.0;
+
+const STARTING_GOLD: f64 = 100.0;
+const GOLD_INCOME: f64 = 5.0;
+
 #[derive(RustEmbed)]
 #[folder = "$OUT_DIR/sprites/"]
 struct SpriteFolder;
@@ -91,6 +137,30 @@ fn main() {
     SpriteFolder::load_sprite(&mut render, &mut resources, "enemy-archer1");
     SpriteFolder::load_sprite(&mut render, &mut resources, "projectile1");
 
+    // The bandits are tinted enemies
+    for name in ["melee1", "archer1"].iter() {
+        let enemy = resources[&format!("enemy-{}", name)];
+        let name = format!("bandit-{}", name);
+        let id = render.add_tinted_buf(&name, enemy, 0x6A_BE_30);
+        resources.insert(name, id);
+    }
+
+    // The other melee units are tinted soldiers
+    for side in ["ally", "enemy", "bandit"].iter() {
+        let melee = resources[&format!("{}-melee1", side)];
+        for (name, tint) in [
+            ("shield1", 0x5B_6E_E1),
+            ("cavalry1", 0x8F_56_3B),
+            ("support1", 0xFF_FF_FF),
+        ]
+        .iter()
+        {
+            let name = format!("{}-{}", side, name);
+            let id = render.add_tinted_buf(&name, melee, *tint);
+            resources.insert(name, id);
+        }
+    }
+
     MaskFolder::load_sprite(&mut render, &mut resources, "bighole1");
 
     // Setup game related things
@@ -102,6 +172,10 @@ fn main() {
     world.register::<Anim>();
     world.register::<Sprite>();
     world.register::<Line>();
+    world.register::<Rect>();
+
+    // emitter.rs
+    world.register::<Emitter>();
 
     // terrain.rs
     world.register::<TerrainMask>();
@@ -115,27 +189,74 @@ fn main() {
 
     // ai.rs
     world.register::<Destination>();
-    world.register::<Ally>();
-    world.register::<Enemy>();
     world.register::<Melee>();
 
     // unit.rs
     world.register::<UnitState>();
+    world.register::<UnitType>();
     world.register::<Health>();
+    world.register::<MaxHealth>();
     world.register::<HealthBar>();
     world.register::<Walk>();
 
+    // faction.rs
+    world.register::<Faction>();
+
+    // armor.rs
+    world.register::<Armor>();
+
+    // cavalry.rs
+    world.register::<Charge>();
+
+    // aura.rs
+    world.register::<Aura>();
+    world.register::<Buffs>();
+
+    // morale.rs
+    world.register::<Morale>();
+
+    // ram.rs
+    world.register::<Ram>();
+
+    // shield.rs
+    world.register::<Shield>();
+
+    // nav.rs
+    world.register::<Route>();
+
     // turret.rs
     world.register::<Turret>();
     world.register::<TurretOffset>();
+    world.register::<Ranged>();
 
     // projectile.rs
     world.register::<Projectile>();
     world.register::<ProjectileSprite>();
     world.register::<ProjectileBoundingBox>();
-    world.register::<IgnoreCollision>();
     world.register::<Arrow>();
     world.register::<Damage>();
+    world.register::<Bounce>();
+    world.register::<Lifetime>();
+    world.register::<StuckInUnit>();
+
+    // explosion.rs
+    world.register::<Explosive>();
+    world.register::<Explosion>();
+    world.register::<Mine>();
+
+    // tower.rs
+    world.register::<TowerSlot>();
+    world.register::<Tower>();
+
+    // climb.rs
+    world.register::<Ladder>();
+    world.register::<SiegeTower>();
+
+    // siege.rs
+    world.register::<PlayerAim>();
+
+    // select.rs
+    world.register::<Selected>();
 
     // gui.rs
     world.register::<FloatingText>();
@@ -146,6 +267,14 @@ fn main() {
     world.insert(DeltaTime::new(1.0 / 60.0));
     world.insert(Images(resources));
     world.insert(Audio::new());
+    world.insert(StuckArrows::default());
+    world.insert(Mouse::default());
+    world.insert(Treasury::new(STARTING_GOLD, GOLD_INCOME));
+    world.insert(WaveSpawner::default());
+    world.insert(Commander::default());
+    world.insert(Order::default());
+    world.insert(Factions::default());
+    world.insert(SelectionBox::default());
 
     render.draw_background_from_memory(&SpriteFolder::get("background.blit").unwrap());
     render.draw_terrain_from_memory(
@@ -153,7 +282,16 @@ fn main() {
         &SpriteFolder::get("level.blit").unwrap(),
     );
 
-    place_turrets(&mut world, 1);
+    // The difficulty of the enemy can be passed as the first argument
+    let difficulty = std::env::args()
+        .nth(1)
+        .and_then(|name| Difficulty::from_name(&name))
+        .unwrap_or_default();
+
+    let nav = NavGrid::new(&world.read_resource::<Terrain>());
+    world.insert(nav);
+
+    place_turrets(&mut world, 1, difficulty);
 
     let mut dispatcher = DispatcherBuilder::new()
         .with(ProjectileSystem, "projectile", &[])
@@ -169,17 +307,42 @@ fn main() {
             &["projectile"],
         )
         .with(TerrainCollapseSystem, "terrain_collapse", &["projectile"])
-        .with(WalkSystem, "walk", &[])
-        .with(UnitFallSystem, "unit_fall", &["walk"])
+        .with(RaidSystem, "raid", &[])
+        .with(NavSystem, "nav", &["raid"])
+        .with(ChargeSystem, "charge", &[])
+        .with(WalkSystem, "walk", &["nav", "charge"])
+        .with(MineSystem, "mine", &["walk"])
+        .with(
+            ExplosionSystem,
+            "explosion",
+            &["projectile_collision", "mine"],
+        )
+        .with(SiegeTowerSystem, "siege_tower", &[])
+        .with(RamSystem, "ram", &["walk"])
+        .with(ClimbSystem, "climb", &["walk", "siege_tower", "ram"])
+        .with(UnitFallSystem, "unit_fall", &["walk", "climb"])
+        .with(
+            StuckInUnitSystem,
+            "stuck_in_unit",
+            &["unit_fall", "projectile_collision"],
+        )
         .with(UnitResumeWalkingSystem, "unit_resume_walking", &["walk"])
         .with(UnitCollideSystem, "unit_collide", &["walk"])
-        .with(MeleeSystem, "melee", &["walk"])
-        .with(HealthBarSystem, "health_bar", &["walk"])
+        .with(AuraSystem, "aura", &["walk"])
+        .with(MoraleSystem::default(), "morale", &["walk", "aura"])
+        .with(MeleeSystem, "melee", &["walk", "morale"])
+        .with(ShieldSystem, "shield", &["walk", "morale"])
+        .with(HealthBarSystem, "health_bar", &["walk", "aura"])
         .with(TurretUnitSystem, "turret_unit", &["walk"])
         .with(TurretSystem, "turret", &["turret_unit"])
+        .with(PlayerAimSystem, "player_aim", &[])
+        .with(IncomeSystem, "income", &[])
+        .with(WaveSystem, "wave", &[])
+        .with(CommanderSystem, "commander", &[])
         .with(SpriteSystem, "sprite", &["projectile", "walk"])
         .with(AnimSystem, "anim", &["projectile", "walk"])
-        .with(ParticleSystem, "particle", &[])
+        .with(EmitterSystem, "emitter", &[])
+        .with(ParticleSystem, "particle", &["emitter"])
         .with(FloatingTextSystem, "floating_text", &[])
         .build();
 
@@ -217,12 +380,65 @@ fn main() {
         }
 
         // Handle mouse events
-        if let Some(mouse) = window.get_mouse_pos(MouseMode::Discard) {
-            gui.handle_mouse(
-                (mouse.0 as i32, mouse.1 as i32),
-                window.get_mouse_down(MouseButton::Left),
-            );
-        };
+        {
+            let mouse_pos = window
+                .get_mouse_pos(MouseMode::Discard)
+                .map(|mouse| (mouse.0 as i32, mouse.1 as i32));
+            let left_is_down = window.get_mouse_down(MouseButton::Left);
+            let right_is_down = window.get_mouse_down(MouseButton::Right);
+
+            let mut mouse = world.write_resource::<Mouse>();
+            mouse.update(mouse_pos, left_is_down, right_is_down);
+            gui.handle_mouse(mouse.pos.as_i32(), left_is_down);
+        }
+
+        // Command the allied units
+        if world.read_resource::<Mouse>().right_pressed() {
+            let x = world.read_resource::<Mouse>().pos.x;
+            give_order(&mut world, Order::Rally(x));
+        } else if window.is_key_pressed(Key::A, KeyRepeat::No) {
+            give_order(&mut world, Order::Advance);
+        } else if window.is_key_pressed(Key::H, KeyRepeat::No) {
+            give_order(&mut world, Order::Hold);
+        } else if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            give_order(&mut world, Order::Retreat);
+        } else if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            cycle_stance(&mut world);
+        }
+
+        // Buy the units that don't have buttons
+        if window.is_key_pressed(Key::Key1, KeyRepeat::No) {
+            buy_medic(&mut world);
+        } else if window.is_key_pressed(Key::Key2, KeyRepeat::No) {
+            buy_banner_bearer(&mut world);
+        } else if window.is_key_pressed(Key::Key3, KeyRepeat::No) {
+            buy_drummer(&mut world);
+        } else if window.is_key_pressed(Key::Key4, KeyRepeat::No) {
+            buy_shield_bearer(&mut world);
+        } else if window.is_key_pressed(Key::Key5, KeyRepeat::No) {
+            buy_battering_ram(&mut world);
+        } else if window.is_key_pressed(Key::Key6, KeyRepeat::No) {
+            buy_cavalry(&mut world);
+        }
+
+        // Build, upgrade or sell the tower under the mouse
+        {
+            let mouse = *world.read_resource::<Mouse>();
+            if let Some(slot) = tower_slot_at(&world, mouse.pos) {
+                if mouse.left_pressed() {
+                    build_or_upgrade_tower(&mut world, slot);
+                } else if window.is_key_pressed(Key::S, KeyRepeat::No) {
+                    sell_tower(&mut world, slot);
+                }
+            }
+        }
+
+        // Select units by dragging a box around them
+        {
+            let mouse = world.read_resource::<Mouse>().pos;
+            let on_gui = gui.contains((mouse.x as i32, mouse.y as i32));
+            update_selection(&mut world, on_gui);
+        }
 
         dispatcher.dispatch(&world);
 
@@ -236,9 +452,14 @@ fn main() {
             let mut anims = world.write_storage::<Anim>();
             let sprites = world.read_storage::<Sprite>();
             let lines = world.read_storage::<Line>();
+            let rects = world.read_storage::<Rect>();
             let pixels = world.read_storage::<PixelParticle>();
             let terrain_masks = world.read_storage::<TerrainMask>();
             let health_bars = world.read_storage::<HealthBar>();
+            let positions = world.read_storage::<WorldPosition>();
+            let aims = world.read_storage::<PlayerAim>();
+            let selected = world.read_storage::<Selected>();
+            let bounding_boxes = world.read_storage::<BoundingBox>();
             for entity in world.entities().join() {
                 if let Some(anim) = anims.get_mut(entity) {
                     render
@@ -252,6 +473,10 @@ fn main() {
                     render.draw_foreground(&mut buffer, sprite).unwrap();
                 }
 
+                if let Some(rect) = rects.get(entity) {
+                    render.draw_foreground_rect(&mut buffer, rect);
+                }
+
                 if let Some(line) = lines.get(entity) {
                     render.draw_foreground_line(&mut buffer, line.p1, line.p2, line.color);
                 }
@@ -266,9 +491,37 @@ fn main() {
                         health_bar.pos,
                         health_bar.health / health_bar.max_health,
                         health_bar.width,
+                        health_bar.healing,
                     );
                 }
 
+                if let (Some(aim), Some(pos)) = (aims.get(entity), positions.get(entity)) {
+                    for point in aim.preview.iter() {
+                        render.draw_foreground_pixel(
+                            &mut buffer,
+                            point.as_usize(),
+                            AIM_PREVIEW_COLOR,
+                        );
+                    }
+
+                    if aim.cooldown_left > 0.0 {
+                        // Show how far the engine is reloaded
+                        let mut bar_pos = pos.0.as_usize();
+                        bar_pos.y = bar_pos.y.saturating_sub(3);
+                        render.draw_healthbar(&mut buffer, bar_pos, aim.reload_ratio(), 5, false);
+                    }
+                }
+
+                if let (Some(_), Some(pos), Some(bb)) = (
+                    selected.get(entity),
+                    positions.get(entity),
+                    bounding_boxes.get(entity),
+                ) {
+                    // Highlight the selected units
+                    let area = *bb + pos.0;
+                    render.draw_selection(&mut buffer, Point(area.min), Point(area.max));
+                }
+
                 if let Some(mask) = terrain_masks.get(entity) {
                     render
                         .draw_mask_terrain(&mut *world.write_resource::<Terrain>(), mask)
@@ -280,6 +533,44 @@ fn main() {
             }
         }
 
+        // Render the rally flag
+        if let Order::Rally(x) = *world.read_resource::<Order>() {
+            let ground = world.read_resource::<Terrain>().ground_height(x as i32);
+            if let Some(y) = ground {
+                let top = f64::from(y) - RALLY_FLAG_HEIGHT;
+
+                // The pole with a small flag at the top
+                render.draw_foreground_line(
+                    &mut buffer,
+                    Point::new(x, top).as_usize(),
+                    Point::new(x, f64::from(y)).as_usize(),
+                    WOOD_COLOR,
+                );
+                for row in 0..3 {
+                    let y = top + f64::from(row);
+                    render.draw_foreground_line(
+                        &mut buffer,
+                        Point::new(x + 1.0, y).as_usize(),
+                        Point::new(x + 5.0, y).as_usize(),
+                        RALLY_FLAG_COLOR,
+                    );
+                }
+            }
+        }
+
+        // Render the selection box while dragging
+        if let Some(start) = world.read_resource::<SelectionBox>().0 {
+            let mouse = world.read_resource::<Mouse>().pos;
+            render.draw_selection(&mut buffer, start, mouse);
+        }
+
+        // Let the units find new paths around the changed terrain
+        let changed = world.write_resource::<Terrain>().take_changed();
+        if !changed.is_empty() {
+            let terrain = world.read_resource::<Terrain>();
+            world.write_resource::<NavGrid>().update(&terrain, changed);
+        }
+
         // Update the gui system and receive a possible event
         match gui.update() {
             GuiEvent::BuyArcherButton => {
@@ -302,6 +593,59 @@ fn main() {
             }
         }
 
+        // Show what will happen when clicking on the tower slot under the mouse
+        let mouse = *world.read_resource::<Mouse>();
+        if let Some(label) =
+            tower_slot_at(&world, mouse.pos).and_then(|slot| tower_slot_label(&world, slot))
+        {
+            gui.draw_label(
+                &mut buffer,
+                &label,
+                (mouse.pos.x as i32, mouse.pos.y as i32 - 10),
+            );
+        }
+
+        // Render the gold of the player
+        let gold = world.read_resource::<Treasury>().gold;
+        gui.draw_label(&mut buffer, &format!("Gold: {}", gold as i32), (4, 4));
+
+        // Render the panel with the selected units
+        {
+            let selected = world.read_storage::<Selected>();
+            let unit_types = world.read_storage::<UnitType>();
+            let stances = world.read_storage::<Stance>();
+
+            let mut counts = (0, 0, 0);
+            let mut stance = None;
+            for (_, unit_type, unit_stance) in (&selected, &unit_types, stances.maybe()).join() {
+                match unit_type {
+                    UnitType::Soldier | UnitType::ShieldBearer | UnitType::Cavalry => counts.0 += 1,
+                    UnitType::Archer => counts.1 += 1,
+                    UnitType::Medic
+                    | UnitType::BannerBearer
+                    | UnitType::Drummer
+                    | UnitType::BatteringRam => counts.2 += 1,
+                }
+                // Only units for which the stance makes a difference have one
+                if let Some(unit_stance) = unit_stance {
+                    stance = Some(*unit_stance);
+                }
+            }
+
+            if counts != (0, 0, 0) {
+                gui.draw_selection_panel(&mut buffer, counts, stance);
+            }
+        }
+
+        // Render the order the allied units are following
+        let order = world.read_resource::<Order>().name();
+        gui.draw_label(&mut buffer, &format!("Order: {}", order), (4, 24));
+
+        // Render the countdown to the next wave
+        if let Some(label) = world.read_resource::<WaveSpawner>().label() {
+            gui.draw_label(&mut buffer, &label, (4, 14));
+        }
+
         // Finally draw the buffer on the window
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
 
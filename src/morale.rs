@@ -0,0 +1,176 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// Units within this distance influence the morale of each other
+const MORALE_RADIUS: f64 = 40.0;
+// The morale lost when an allied unit dies nearby
+const DEATH_PENALTY: f64 = 15.0;
+// The morale lost for every point of health lost
+const DAMAGE_PENALTY: f64 = 1.0;
+// The morale lost every second for every enemy more than there are allies nearby
+const OUTNUMBERED_PENALTY: f64 = 3.0;
+// The morale regained every second
+const RECOVERY: f64 = 2.0;
+// Routing units return to the fight when their morale is back above this part of the maximum
+const RALLY_RATIO: f64 = 0.5;
+// The damage done by units without any morale compared to units with full morale
+const MIN_EFFECTIVENESS: f64 = 0.5;
+
+/// The will of a unit to keep fighting, it flees when it reaches zero.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Morale {
+    pub value: f64,
+    pub max: f64,
+    /// The x position of the base of the faction, where the unit flees to.
+    pub home: f64,
+
+    last_health: Option<f64>,
+}
+
+impl Morale {
+    pub fn new(max: f64, home: f64) -> Self {
+        Morale {
+            value: max,
+            max,
+            home,
+
+            last_health: None,
+        }
+    }
+
+    /// The direction to flee in from the x position, 0 when already home.
+    pub fn flee_dir(&self, x: f64) -> f64 {
+        if (self.home - x).abs() < 1.0 {
+            0.0
+        } else {
+            (self.home - x).signum()
+        }
+    }
+}
+
+/// How much damage a unit does in melee with the morale.
+pub fn morale_effectiveness(morale: Option<&Morale>) -> f64 {
+    morale.map_or(1.0, |morale| {
+        MIN_EFFECTIVENESS + (1.0 - MIN_EFFECTIVENESS) * morale.value / morale.max
+    })
+}
+
+#[derive(SystemData)]
+pub struct MoraleSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    updater: Read<'a, LazyUpdate>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    buffs: ReadStorage<'a, Buffs>,
+    pos: ReadStorage<'a, WorldPosition>,
+    health: ReadStorage<'a, Health>,
+    morale: WriteStorage<'a, Morale>,
+    state: WriteStorage<'a, UnitState>,
+}
+
+/// Keeps the units of the previous frame to find out which of them died.
+#[derive(Default)]
+pub struct MoraleSystem {
+    units: Vec<(Entity, Point, Option<Faction>)>,
+}
+
+impl<'a> System<'a> for MoraleSystem {
+    type SystemData = MoraleSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let factions = &system_data.factions;
+
+        // The units that were there the last frame but aren't anymore
+        let deaths: Vec<(Point, Option<Faction>)> = self
+            .units
+            .iter()
+            .filter(|(unit, _, _)| !system_data.entities.is_alive(*unit))
+            .map(|(_, pos, faction)| (*pos, *faction))
+            .collect();
+
+        self.units = (
+            &*system_data.entities,
+            &system_data.pos,
+            &system_data.morale,
+        )
+            .join()
+            .map(|(unit, pos, _)| (unit, pos.0, system_data.faction.get(unit).copied()))
+            .collect();
+
+        for (unit, pos, health, morale, state) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &system_data.health,
+            &mut system_data.morale,
+            &mut system_data.state,
+        )
+            .join()
+        {
+            let faction = system_data.faction.get(unit);
+            let nearby = |other: Point| pos.0.distance(*other) < MORALE_RADIUS;
+
+            let mut change = RECOVERY * dt;
+
+            // Taking damage from any source is frightening
+            if let Some(last_health) = morale.last_health {
+                change -= (last_health - health.0).max(0.0) * DAMAGE_PENALTY;
+            }
+            morale.last_health = Some(health.0);
+
+            change -= deaths
+                .iter()
+                .filter(|(death_pos, death_faction)| {
+                    factions.allied(faction, death_faction.as_ref()) && nearby(*death_pos)
+                })
+                .count() as f64
+                * DEATH_PENALTY;
+
+            // Compare the amount of allied and hostile units around it, neutral units don't count
+            let (friends, foes) = self
+                .units
+                .iter()
+                .filter(|(other, other_pos, _)| *other != unit && nearby(*other_pos))
+                .fold(
+                    (0, 0),
+                    |(friends, foes), (_, _, other_faction)| match factions
+                        .relation(faction, other_faction.as_ref())
+                    {
+                        Relation::Allied => (friends + 1, foes),
+                        Relation::Hostile => (friends, foes + 1),
+                        Relation::Neutral => (friends, foes),
+                    },
+                );
+            if foes > friends + 1 {
+                change -= (foes - friends - 1) as f64 * OUTNUMBERED_PENALTY * dt;
+            }
+
+            // Leaders, banners and drums encourage the units around them
+            if let Some(buffs) = system_data.buffs.get(unit) {
+                change += buffs.morale * dt;
+            }
+
+            morale.value = (morale.value + change).clamp(0.0, morale.max);
+
+            if morale.value <= 0.0 && *state != UnitState::Rout {
+                // The morale breaks
+                *state = UnitState::Rout;
+
+                system_data.updater.insert(
+                    system_data.entities.create(),
+                    FloatingText {
+                        text: "!".to_string(),
+                        pos: pos.0,
+                        time_alive: 1.0,
+                    },
+                );
+            } else if *state == UnitState::Rout && morale.value >= morale.max * RALLY_RATIO {
+                *state = UnitState::Walk;
+            }
+        }
+    }
+}
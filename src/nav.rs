@@ -0,0 +1,394 @@
+use specs::prelude::*;
+use specs_derive::Component;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap},
+};
+
+use super::*;
+
+// The free space a unit needs above a surface to stand on it
+const UNIT_CLEARANCE: i32 = 10;
+// The widest gap a unit can jump over
+const MAX_JUMP_DISTANCE: i32 = 8;
+// The upwards speed of a unit when it jumps
+pub const JUMP_SPEED: f64 = 40.0;
+// The extra cost of every pixel climbed, climbing is slow
+const CLIMB_COST: u32 = 3;
+// Forget the paths when there are more destinations than this
+const MAX_FLOWS: usize = 16;
+
+/// Which way a unit needs to walk to follow the path to its destination.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct Route {
+    /// -1 to walk left, 1 to walk right and 0 when the unit should hold.
+    pub dir: f64,
+    /// There is a gap in front of the unit it needs to jump over.
+    pub jump: bool,
+}
+
+/// The direction a unit walks in, following its route when it has one.
+pub fn walk_dir(route: Option<&Route>, dest: &Destination, pos: &WorldPosition) -> f64 {
+    route.map_or_else(|| (dest.0 - pos.0.x).signum(), |route| route.dir)
+}
+
+/// A way to get from a surface to a surface in another column.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Edge {
+    /// The index of the surface it leads to.
+    to: usize,
+    dir: i32,
+    jump: bool,
+    cost: u32,
+}
+
+/// The surfaces units can walk on, built from the terrain.
+#[derive(Debug, Default)]
+pub struct NavGrid {
+    /// The heights of the surfaces in every column, from top to bottom.
+    surfaces: Vec<Vec<i32>>,
+    /// The index of the first surface of every column in the list of all surfaces.
+    offsets: Vec<usize>,
+    /// The ways to leave every surface.
+    edges: Vec<Vec<Edge>>,
+    /// The areas as (x, y, width, height) in which units can climb walls of any height.
+    ladders: Vec<(i32, i32, i32, i32)>,
+    /// The first step towards the destination column for every surface, `None` when the
+    /// destination can't be reached from the surface.
+    flows: HashMap<i32, Vec<Option<Route>>>,
+}
+
+impl NavGrid {
+    pub fn new(terrain: &Terrain) -> Self {
+        let mut nav = NavGrid::default();
+        nav.update(terrain, 0..terrain.size().0);
+
+        nav
+    }
+
+    /// Find the surfaces again in the columns that changed.
+    pub fn update(&mut self, terrain: &Terrain, columns: impl IntoIterator<Item = usize>) {
+        let width = terrain.size().0;
+        self.surfaces.resize(width, Vec::new());
+
+        let mut changed = BTreeSet::new();
+        let mut added_or_removed = false;
+        for x in columns.into_iter().filter(|x| *x < width) {
+            let surfaces = column_surfaces(terrain, x as i32);
+            if surfaces != self.surfaces[x] {
+                added_or_removed |= surfaces.len() != self.surfaces[x].len();
+                self.surfaces[x] = surfaces;
+                changed.insert(x as i32);
+            }
+        }
+
+        let reconnected = if added_or_removed {
+            // The indices of the surfaces moved, connect all of them again
+            self.offsets = self
+                .surfaces
+                .iter()
+                .scan(0, |offset, column| {
+                    let first = *offset;
+                    *offset += column.len();
+
+                    Some(first)
+                })
+                .collect();
+            let nodes = self.surfaces.iter().map(|column| column.len()).sum();
+            self.edges = vec![Vec::new(); nodes];
+
+            self.connect(terrain, 0..width as i32)
+        } else {
+            // Only the surfaces that can walk or jump to the changed columns are affected
+            let columns: BTreeSet<i32> = changed
+                .iter()
+                .flat_map(|x| x - MAX_JUMP_DISTANCE..=x + MAX_JUMP_DISTANCE)
+                .collect();
+
+            self.connect(terrain, columns)
+        };
+
+        if reconnected {
+            self.flows.clear();
+        }
+    }
+
+    /// Set the climbable areas, only the edges next to the ladders that moved are found again.
+    pub fn set_ladders(&mut self, terrain: &Terrain, ladders: Vec<(i32, i32, i32, i32)>) {
+        if ladders == self.ladders {
+            return;
+        }
+
+        // The columns next to the ladders that were added, removed or moved
+        let columns: BTreeSet<i32> = ladders
+            .iter()
+            .filter(|ladder| !self.ladders.contains(ladder))
+            .chain(
+                self.ladders
+                    .iter()
+                    .filter(|ladder| !ladders.contains(ladder)),
+            )
+            .flat_map(|(x, _, width, _)| x - 1..=x + width + 1)
+            .collect();
+        self.ladders = ladders;
+
+        if self.connect(terrain, columns) {
+            self.flows.clear();
+        }
+    }
+
+    /// Find the edges of the surfaces in the columns again, returns whether any of them changed.
+    fn connect(&mut self, terrain: &Terrain, columns: impl IntoIterator<Item = i32>) -> bool {
+        let width = self.surfaces.len() as i32;
+
+        let mut changed = false;
+        for x in columns.into_iter().filter(|x| *x >= 0 && *x < width) {
+            for surface in 0..self.surfaces[x as usize].len() {
+                let edges = self.find_edges(terrain, x, surface);
+                let node = self.node_index(x, surface);
+                if edges != self.edges[node] {
+                    self.edges[node] = edges;
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Find the route of a unit standing on the position towards the destination.
+    pub fn route(&mut self, feet: (i32, i32), dest: f64) -> Option<Route> {
+        let width = self.surfaces.len() as i32;
+        if width == 0 {
+            return None;
+        }
+        let (x, y) = feet;
+        if x < 0 || x >= width {
+            return None;
+        }
+
+        // Find the surface the unit is standing on, it's in the air if there is none
+        let surface = self.surfaces[x as usize]
+            .iter()
+            .position(|sy| *sy >= y - STEP_HEIGHT as i32)
+            .filter(|index| self.surfaces[x as usize][*index] <= y + STEP_HEIGHT as i32)?;
+
+        let dest = (dest as i32).clamp(0, width - 1);
+        if self.flows.len() > MAX_FLOWS {
+            self.flows.clear();
+        }
+        if !self.flows.contains_key(&dest) {
+            let flow = self.flow(dest);
+            self.flows.insert(dest, flow);
+        }
+
+        let node = self.node_index(x, surface);
+        if let Some(route) = self.flows[&dest][node] {
+            return Some(route);
+        }
+
+        // There is no way to the destination, keep going as far as possible and hold when blocked
+        let dir = (dest - x).signum();
+        Some(
+            self.edges[node]
+                .iter()
+                .find(|edge| edge.dir == dir)
+                .map_or_else(Route::default, |edge| Route {
+                    dir: f64::from(edge.dir),
+                    jump: edge.jump,
+                }),
+        )
+    }
+
+    /// The index of the surface in the list of all the surfaces.
+    fn node_index(&self, x: i32, surface: usize) -> usize {
+        self.offsets[x as usize] + surface
+    }
+
+    fn on_ladder(&self, x: i32, y: i32) -> bool {
+        self.ladders
+            .iter()
+            .any(|(lx, ly, lw, lh)| x >= *lx && x <= lx + lw && y >= *ly && y <= ly + lh)
+    }
+
+    /// Find the ways to walk, climb, drop or jump to the neighbouring columns.
+    fn find_edges(&self, terrain: &Terrain, x: i32, surface: usize) -> Vec<Edge> {
+        let width = self.surfaces.len() as i32;
+        let y = self.surfaces[x as usize][surface];
+
+        let mut edges = Vec::new();
+        for dir in [-1, 1].iter().copied() {
+            let nx = x + dir;
+            if nx < 0 || nx >= width {
+                continue;
+            }
+
+            let climb = if self.on_ladder(x, y) || self.on_ladder(nx, y) {
+                y
+            } else {
+                MAX_CLIMB_HEIGHT as i32
+            };
+
+            // The highest surface the unit can climb onto or the one it drops to
+            let column = &self.surfaces[nx as usize];
+            match column.iter().position(|ny| *ny >= y - climb) {
+                Some(index) if column[index] - y <= FALL_DAMAGE_HEIGHT as i32 => {
+                    let height = (y - column[index]).max(0) as u32;
+
+                    edges.push(Edge {
+                        to: self.node_index(nx, index),
+                        dir,
+                        jump: false,
+                        cost: 1 + height * CLIMB_COST,
+                    });
+                }
+                _ if !terrain.is_solid((nx, y - 1)) => {
+                    // A gap or a deep drop, try to jump over it
+                    let landing = (2..=MAX_JUMP_DISTANCE)
+                        .map(|dist| x + dir * dist)
+                        .take_while(|jx| *jx >= 0 && *jx < width)
+                        .find_map(|jx| {
+                            self.surfaces[jx as usize]
+                                .iter()
+                                .position(|jy| (jy - y).abs() <= STEP_HEIGHT as i32)
+                                .map(|index| (jx, index))
+                        });
+
+                    if let Some((jx, index)) = landing {
+                        edges.push(Edge {
+                            to: self.node_index(jx, index),
+                            dir,
+                            jump: true,
+                            cost: 2 * (jx - x).unsigned_abs(),
+                        });
+                    }
+                }
+                // A wall that's too high
+                _ => (),
+            }
+        }
+
+        edges
+    }
+
+    /// Calculate the first step towards the destination column from every surface.
+    fn flow(&self, dest: i32) -> Vec<Option<Route>> {
+        let nodes = self.edges.len();
+
+        // Search backwards from the destination so every edge needs to be reversed
+        let mut incoming = vec![Vec::new(); nodes];
+        for (node, edges) in self.edges.iter().enumerate() {
+            for edge in edges {
+                incoming[edge.to].push((node, *edge));
+            }
+        }
+
+        let mut cost = vec![u32::MAX; nodes];
+        let mut flow = vec![None; nodes];
+        let mut queue = BinaryHeap::new();
+
+        let first = self.node_index(dest, 0);
+        for node in first..first + self.surfaces[dest as usize].len() {
+            cost[node] = 0;
+            flow[node] = Some(Route::default());
+            queue.push(Reverse((0, node)));
+        }
+
+        while let Some(Reverse((node_cost, node))) = queue.pop() {
+            if node_cost > cost[node] {
+                continue;
+            }
+
+            for (from, edge) in incoming[node].iter() {
+                let new_cost = node_cost + edge.cost;
+                if new_cost < cost[*from] {
+                    cost[*from] = new_cost;
+                    flow[*from] = Some(Route {
+                        dir: f64::from(edge.dir),
+                        jump: edge.jump,
+                    });
+                    queue.push(Reverse((new_cost, *from)));
+                }
+            }
+        }
+
+        flow
+    }
+}
+
+/// Find the solid pixels in the column with enough free space above them to stand on.
+fn column_surfaces(terrain: &Terrain, x: i32) -> Vec<i32> {
+    let height = terrain.size().1 as i32;
+
+    // The top of the map counts as open air
+    let mut free = UNIT_CLEARANCE;
+    let mut surfaces = Vec::new();
+    for y in 0..height {
+        if terrain.is_solid((x, y)) {
+            if free >= UNIT_CLEARANCE {
+                surfaces.push(y);
+            }
+            free = 0;
+        } else {
+            free += 1;
+        }
+    }
+
+    surfaces
+}
+
+#[derive(SystemData)]
+pub struct NavSystemData<'a> {
+    terrain: Read<'a, Terrain>,
+    nav: Write<'a, NavGrid>,
+    ladder: ReadStorage<'a, Ladder>,
+    dest: ReadStorage<'a, Destination>,
+    walk: ReadStorage<'a, Walk>,
+    pos: ReadStorage<'a, WorldPosition>,
+    route: WriteStorage<'a, Route>,
+}
+
+pub struct NavSystem;
+impl<'a> System<'a> for NavSystem {
+    type SystemData = NavSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let terrain = &system_data.terrain;
+        let nav = &mut system_data.nav;
+
+        nav.set_ladders(
+            terrain,
+            (&system_data.ladder, &system_data.pos)
+                .join()
+                .map(|(ladder, pos)| {
+                    (
+                        pos.0.x as i32,
+                        pos.0.y as i32,
+                        ladder.width as i32,
+                        ladder.height as i32,
+                    )
+                })
+                .collect(),
+        );
+
+        for (dest, walk, pos, route) in (
+            &system_data.dest,
+            &system_data.walk,
+            &system_data.pos,
+            &mut system_data.route,
+        )
+            .join()
+        {
+            // The middle of the bottom of the unit
+            let feet = (
+                (pos.0.x + (walk.bounds.min.x + walk.bounds.max.x) / 2.0) as i32,
+                (pos.0.y + walk.bounds.max.y) as i32,
+            );
+
+            // Keep the old route while the unit is in the air
+            if let Some(new_route) = nav.route(feet, dest.0) {
+                *route = new_route;
+            }
+        }
+    }
+}
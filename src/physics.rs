@@ -61,13 +61,17 @@ impl<'a> System<'a> for ParticleSystem {
         {
             pos.0.x += vel.x * dt;
             pos.0.y += vel.y * dt;
-            vel.y += grav * dt;
+            vel.y += grav * par.gravity_scale * dt;
 
             let old_pos = par.pos;
-            match system_data
-                .terrain
-                .line_collides(pos.0.as_i32(), (old_pos.x as i32, old_pos.y as i32))
-            {
+            let collision = if par.collides {
+                system_data
+                    .terrain
+                    .line_collides(pos.0.as_i32(), (old_pos.x as i32, old_pos.y as i32))
+            } else {
+                None
+            };
+            match collision {
                 Some(point) => {
                     system_data
                         .terrain
@@ -80,6 +84,7 @@ impl<'a> System<'a> for ParticleSystem {
                     if par.life < 0.0 {
                         let _ = system_data.entities.delete(entity);
                     }
+                    par.update_color();
                 }
             }
         }
@@ -1,21 +1,26 @@
 use crate::audio::Audio;
+use cgmath::Point2;
 use collision::Discrete;
-use rand::{
-    self,
-    distributions::{Distribution, Uniform},
-};
 use specs::prelude::*;
 use specs_derive::Component;
+use std::collections::VecDeque;
 
 use super::*;
 
-const BLOOD_COLOR: u32 = 0xAC_32_33;
+// How much of the velocity of a projectile times the damage is transferred to the unit it hits
+const HIT_KNOCKBACK: f64 = 0.01;
 
-#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
-pub enum IgnoreCollision {
-    Enemy,
-    Ally,
-}
+// How many seconds a projectile can fly before it's removed
+pub const PROJECTILE_LIFETIME: f64 = 30.0;
+// How far projectiles can fly above the top of the world before they are removed
+const WORLD_TOP_MARGIN: f64 = 1000.0;
+// The maximum amount of arrows stuck in the ground, the oldest ones are removed first
+const MAX_STUCK_ARROWS: usize = 200;
+// The speed an arrow dropped by a dead unit starts falling with in the direction it points
+const DROP_SPEED: f64 = 1.0;
+
+// The radius in pixels used to calculate the normal of the terrain surface
+const BOUNCE_NORMAL_RADIUS: i32 = 3;
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Projectile;
@@ -29,20 +34,164 @@ pub struct ProjectileBoundingBox(pub BoundingBox);
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Arrow(pub f64);
 
+/// The damage and the type of damage done by a hit.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Damage(pub f64, pub DamageType);
+
+/// How many times bigger than the mask the crater of a projectile is.
+fn crater_scale(damage: Option<&Damage>) -> usize {
+    damage.map_or(1, |damage| damage.1.crater_scale())
+}
+
+/// The time in seconds a projectile has left before it's removed.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Lifetime(pub f64);
+
+/// An arrow stuck in a unit, it moves along with the unit and drops to the ground when the unit
+/// dies.
 #[derive(Component, Debug, Copy, Clone)]
-pub struct Damage(pub f64);
+pub struct StuckInUnit {
+    pub unit: Entity,
+    /// The offsets of both points of the line relative to the position of the unit.
+    pub offset: ((f64, f64), (f64, f64)),
+}
+
+/// The arrows stuck in the ground or dropping to it, ordered from old to new.
+#[derive(Default)]
+pub struct StuckArrows(pub VecDeque<Entity>);
+
+impl StuckArrows {
+    /// Keep track of the arrow and remove the oldest arrows when there are too many.
+    pub fn push(&mut self, entities: &Entities, arrow: Entity) {
+        self.0.retain(|arrow| entities.is_alive(*arrow));
+        self.0.push_back(arrow);
+        while self.0.len() > MAX_STUCK_ARROWS {
+            if let Some(oldest) = self.0.pop_front() {
+                let _ = entities.delete(oldest);
+            }
+        }
+    }
+}
+
+/// A projectile that bounces off the terrain and rolls down slopes instead of being destroyed on
+/// impact.
+#[derive(Component, Debug, Clone)]
+pub struct Bounce {
+    /// The fraction of the velocity perpendicular to the surface that is kept on impact.
+    pub restitution: f64,
+    /// How fast the velocity along the surface is lost per second while rolling.
+    pub friction: f64,
+    /// When the projectile touches the ground below this speed it comes to rest.
+    pub rest_speed: f64,
+
+    /// How many times the projectile hit the terrain, only the first impact leaves a crater.
+    pub impacts: usize,
+    /// The units that are already damaged by this projectile.
+    pub hit: Vec<Entity>,
+}
+
+impl Bounce {
+    pub fn new(restitution: f64, friction: f64, rest_speed: f64) -> Self {
+        Bounce {
+            restitution,
+            friction,
+            rest_speed,
+
+            impacts: 0,
+            hit: Vec::new(),
+        }
+    }
+
+    /// Reflect the velocity off the surface with the normal.
+    pub fn reflect(&self, vel: &mut Velocity, normal: (f64, f64), dt: f64) {
+        let dot = vel.x * normal.0 + vel.y * normal.1;
+        if dot >= 0.0 {
+            // Already moving away from the surface
+            return;
+        }
+
+        // Split the velocity into a part along the normal and along the surface
+        let (nx, ny) = (dot * normal.0, dot * normal.1);
+        let friction = (1.0 - self.friction * dt).max(0.0);
+        let (tx, ty) = ((vel.x - nx) * friction, (vel.y - ny) * friction);
+
+        vel.x = tx - nx * self.restitution;
+        vel.y = ty - ny * self.restitution;
+    }
+}
+
+/// The components of an entity describing the projectiles it fires.
+#[derive(SystemData)]
+pub struct ProjectileTemplates<'a> {
+    sprite: ReadStorage<'a, ProjectileSprite>,
+    arrow: ReadStorage<'a, Arrow>,
+    line: ReadStorage<'a, Line>,
+    mask: ReadStorage<'a, MaskId>,
+    bounce: ReadStorage<'a, Bounce>,
+    explosive: ReadStorage<'a, Explosive>,
+    faction: ReadStorage<'a, Faction>,
+    bb: ReadStorage<'a, ProjectileBoundingBox>,
+    dmg: ReadStorage<'a, Damage>,
+}
+
+impl<'a> ProjectileTemplates<'a> {
+    /// Fire a projectile with the projectile components of the template entity.
+    pub fn fire(
+        &self,
+        entities: &Entities,
+        updater: &LazyUpdate,
+        template: Entity,
+        pos: Point,
+        vel: Velocity,
+    ) {
+        let projectile = entities.create();
+        updater.insert(projectile, Projectile);
+        updater.insert(projectile, Lifetime(PROJECTILE_LIFETIME));
+        updater.insert(projectile, WorldPosition(pos));
+        updater.insert(projectile, vel);
+
+        if let Some(bb) = self.bb.get(template) {
+            updater.insert(projectile, *bb);
+        }
+        if let Some(dmg) = self.dmg.get(template) {
+            updater.insert(projectile, *dmg);
+        }
+        if let Some(mask) = self.mask.get(template) {
+            updater.insert(projectile, *mask);
+        }
+        if let Some(bounce) = self.bounce.get(template) {
+            updater.insert(projectile, bounce.clone());
+        }
+        if let Some(explosive) = self.explosive.get(template) {
+            updater.insert(projectile, *explosive);
+        }
+        if let Some(sprite) = self.sprite.get(template) {
+            updater.insert(projectile, sprite.0);
+        }
+        if let Some(arrow) = self.arrow.get(template) {
+            updater.insert(projectile, *arrow);
+        }
+        if let Some(line) = self.line.get(template) {
+            updater.insert(projectile, *line);
+        }
+        if let Some(faction) = self.faction.get(template) {
+            updater.insert(projectile, *faction);
+        }
+    }
+}
 
 pub struct ArrowSystem;
 impl<'a> System<'a> for ArrowSystem {
     type SystemData = (
+        ReadStorage<'a, Projectile>,
         ReadStorage<'a, WorldPosition>,
         ReadStorage<'a, Velocity>,
         WriteStorage<'a, Arrow>,
         WriteStorage<'a, Line>,
     );
 
-    fn run(&mut self, (pos, vel, mut arrow, mut line): Self::SystemData) {
-        for (pos, vel, arrow, line) in (&pos, &vel, &mut arrow, &mut line).join() {
+    fn run(&mut self, (proj, pos, vel, mut arrow, mut line): Self::SystemData) {
+        for (_, pos, vel, arrow, line) in (&proj, &pos, &vel, &mut arrow, &mut line).join() {
             let rot = (vel.y as f64).atan2(vel.x as f64);
 
             line.p1.x = pos.0.x as usize;
@@ -61,8 +210,13 @@ pub struct ProjectileSystemData<'a> {
     grav: Read<'a, Gravity>,
     terrain: Read<'a, Terrain>,
     audio: Read<'a, Audio>,
+    stuck: Write<'a, StuckArrows>,
     proj: ReadStorage<'a, Projectile>,
     mask: ReadStorage<'a, MaskId>,
+    explosive: ReadStorage<'a, Explosive>,
+    dmg: ReadStorage<'a, Damage>,
+    bounce: WriteStorage<'a, Bounce>,
+    lifetime: WriteStorage<'a, Lifetime>,
     line: WriteStorage<'a, Line>,
     vel: WriteStorage<'a, Velocity>,
     pos: WriteStorage<'a, WorldPosition>,
@@ -76,6 +230,7 @@ impl<'a> System<'a> for ProjectileSystem {
     fn run(&mut self, mut system_data: Self::SystemData) {
         let grav = system_data.grav.0;
         let dt = system_data.dt.to_seconds();
+        let (width, height) = system_data.terrain.size();
 
         for (entity, _, vel, pos) in (
             &*system_data.entities,
@@ -85,6 +240,24 @@ impl<'a> System<'a> for ProjectileSystem {
         )
             .join()
         {
+            if let Some(lifetime) = system_data.lifetime.get_mut(entity) {
+                lifetime.0 -= dt;
+                if lifetime.0 <= 0.0 {
+                    let _ = system_data.entities.delete(entity);
+                    continue;
+                }
+            }
+
+            // Remove the projectile when it left the world
+            if pos.0.x < 0.0
+                || pos.0.x >= width as f64
+                || pos.0.y >= height as f64
+                || pos.0.y < -WORLD_TOP_MARGIN
+            {
+                let _ = system_data.entities.delete(entity);
+                continue;
+            }
+
             let next: Point = Point::new(pos.0.x + vel.x * dt, pos.0.y + vel.y * dt);
 
             match system_data
@@ -92,16 +265,86 @@ impl<'a> System<'a> for ProjectileSystem {
                 .line_collides(pos.0.as_i32(), next.as_i32())
             {
                 Some(point) => {
-                    if point.0 < 0 || point.1 < 0 {
+                    if let Some(explosive) = system_data.explosive.get(entity) {
+                        // Explosives blow up on impact and make their own crater
+                        system_data.updater.insert(
+                            system_data.entities.create(),
+                            Explosion::new(
+                                Point::new(f64::from(point.0), f64::from(point.1)),
+                                explosive,
+                                system_data
+                                    .dmg
+                                    .get(entity)
+                                    .map_or(Damage(0.0, DamageType::Fire), |dmg| *dmg),
+                                system_data.mask.get(entity).copied(),
+                            ),
+                        );
+
                         let _ = system_data.entities.delete(entity);
                         continue;
                     }
 
+                    if let Some(bounce) = system_data.bounce.get_mut(entity) {
+                        if bounce.impacts == 0 {
+                            if let Some(mask) = system_data.mask.get(entity) {
+                                // Only create a crater on the first impact
+                                system_data.updater.insert(
+                                    system_data.entities.create(),
+                                    TerrainMask::new(mask.id, point, mask.size)
+                                        .with_scale(crater_scale(system_data.dmg.get(entity))),
+                                );
+                            }
+
+                            // Play a sound
+                            system_data.audio.play_heavy_projectile();
+                        }
+                        bounce.impacts += 1;
+
+                        // Kick up some dust where it touches the ground
+                        if (vel.x * vel.x + vel.y * vel.y).sqrt() > bounce.rest_speed * 4.0 {
+                            Emitter::spawn(
+                                &system_data.entities,
+                                &system_data.updater,
+                                DUST,
+                                Point::new(pos.0.x, pos.0.y),
+                            );
+                        }
+
+                        match system_data.terrain.normal(point, BOUNCE_NORMAL_RADIUS) {
+                            Some(normal) => bounce.reflect(vel, normal, dt),
+                            None => {
+                                // The projectile is buried in the terrain
+                                let _ = system_data.entities.delete(entity);
+                                continue;
+                            }
+                        }
+
+                        if (vel.x * vel.x + vel.y * vel.y).sqrt() < bounce.rest_speed {
+                            // The projectile came to rest
+                            let _ = system_data.entities.delete(entity);
+                            continue;
+                        }
+
+                        // Move along the surface if the new direction is not obstructed
+                        let next: Point = Point::new(pos.0.x + vel.x * dt, pos.0.y + vel.y * dt);
+                        if system_data
+                            .terrain
+                            .line_collides(pos.0.as_i32(), next.as_i32())
+                            .is_none()
+                        {
+                            pos.0 = next;
+                        }
+                        vel.y += grav * dt;
+
+                        continue;
+                    }
+
                     if let Some(mask) = system_data.mask.get(entity) {
                         // Create a crater if there is a mask for it
                         system_data.updater.insert(
                             system_data.entities.create(),
-                            TerrainMask::new(mask.id, point, mask.size),
+                            TerrainMask::new(mask.id, point, mask.size)
+                                .with_scale(crater_scale(system_data.dmg.get(entity))),
                         );
 
                         // Play a sound
@@ -122,9 +365,9 @@ impl<'a> System<'a> for ProjectileSystem {
                             line_copy.p2.x = (line_copy.p2.x as i32 + dx) as usize;
                             line_copy.p2.y = (line_copy.p2.y as i32 + dy) as usize;
 
-                            system_data
-                                .updater
-                                .insert(system_data.entities.create(), line_copy);
+                            let stuck_arrow = system_data.entities.create();
+                            system_data.updater.insert(stuck_arrow, line_copy);
+                            system_data.stuck.push(&system_data.entities, stuck_arrow);
                         }
 
                         // Play a sound
@@ -152,9 +395,15 @@ pub struct ProjectileCollisionSystemData<'a> {
     proj_bb: ReadStorage<'a, ProjectileBoundingBox>,
     bb: ReadStorage<'a, BoundingBox>,
     dmg: ReadStorage<'a, Damage>,
-    ignore: ReadStorage<'a, IgnoreCollision>,
-    ally: ReadStorage<'a, Ally>,
-    enemy: ReadStorage<'a, Enemy>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    explosive: ReadStorage<'a, Explosive>,
+    mask: ReadStorage<'a, MaskId>,
+    line: ReadStorage<'a, Line>,
+    shield: ReadStorage<'a, Shield>,
+    armor: ReadStorage<'a, Armor>,
+    bounce: WriteStorage<'a, Bounce>,
+    vel: WriteStorage<'a, Velocity>,
     health: WriteStorage<'a, Health>,
 }
 
@@ -181,27 +430,97 @@ impl<'a> System<'a> for ProjectileCollisionSystem {
             )
                 .join()
             {
-                let ignore_e: Option<&IgnoreCollision> = system_data.ignore.get(proj);
-                if let Some(ignore) = ignore_e {
-                    if *ignore == IgnoreCollision::Ally {
-                        let is_ally: Option<&Ally> = system_data.ally.get(target);
-                        if is_ally.is_some() {
-                            continue;
-                        }
-                    }
-                    if *ignore == IgnoreCollision::Enemy {
-                        let is_enemy: Option<&Enemy> = system_data.enemy.get(target);
-                        if is_enemy.is_some() {
-                            continue;
-                        }
+                // Projectiles fired by a faction only hit its hostile units
+                if let Some(faction) = system_data.faction.get(proj) {
+                    if !system_data
+                        .factions
+                        .hostile(Some(faction), system_data.faction.get(target))
+                    {
+                        continue;
                     }
                 }
 
                 // When there is a collision with a unit
                 let target_aabb = *target_bb + *target_pos.0;
                 if proj_aabb.intersects(&*target_aabb) {
-                    if reduce_unit_health(&system_data.entities, target, target_health, proj_dmg.0)
+                    if let Some(explosive) = system_data.explosive.get(proj) {
+                        // Explosives don't damage the unit directly but blow up on it
+                        system_data.updater.insert(
+                            system_data.entities.create(),
+                            Explosion::new(
+                                proj_pos.0,
+                                explosive,
+                                *proj_dmg,
+                                system_data.mask.get(proj).copied(),
+                            ),
+                        );
+
+                        let _ = system_data.entities.delete(proj);
+                        break;
+                    }
+
+                    // Shields stop arrows and bolts but not rolling boulders
+                    if let (Some(shield), Some(proj_vel), None) = (
+                        system_data.shield.get(target),
+                        system_data.vel.get(proj),
+                        system_data.bounce.get(proj),
+                    ) {
+                        if shield.blocks(proj_vel) {
+                            let _ = system_data.entities.delete(proj);
+
+                            Emitter::spawn(
+                                &system_data.entities,
+                                &system_data.updater,
+                                SPARKS,
+                                proj_pos.0,
+                            );
+                            break;
+                        }
+                    }
+
+                    let bounce: Option<&mut Bounce> = system_data.bounce.get_mut(proj);
+                    if let Some(bounce) = bounce {
+                        // A bouncing projectile rolls through units but only hits them once
+                        if bounce.hit.contains(&target) {
+                            continue;
+                        }
+                        bounce.hit.push(target);
+                    } else {
+                        let _ = system_data.entities.delete(proj);
+                    }
+
+                    if let Some(line) = system_data.line.get(proj) {
+                        // Let the arrow stick in the unit
+                        let offset = |p: Point2<usize>| {
+                            (p.x as f64 - target_pos.0.x, p.y as f64 - target_pos.0.y)
+                        };
+                        let stuck_arrow = system_data.entities.create();
+                        system_data.updater.insert(stuck_arrow, *line);
+                        system_data.updater.insert(
+                            stuck_arrow,
+                            StuckInUnit {
+                                unit: target,
+                                offset: (offset(line.p1), offset(line.p2)),
+                            },
+                        );
+                    }
+
+                    // Push the unit away in the direction the projectile was flying
+                    let proj_vel = system_data.vel.get(proj).copied();
+                    if let (Some(proj_vel), Some(target_vel)) =
+                        (proj_vel, system_data.vel.get_mut(target))
                     {
+                        target_vel.x += proj_vel.x * proj_dmg.0 * HIT_KNOCKBACK;
+                        target_vel.y += proj_vel.y * proj_dmg.0 * HIT_KNOCKBACK;
+                    }
+
+                    if reduce_unit_health(
+                        &system_data.entities,
+                        target,
+                        target_health,
+                        system_data.armor.get(target),
+                        *proj_dmg,
+                    ) {
                         // The ally died
                         system_data.updater.insert(
                             system_data.entities.create(),
@@ -213,21 +532,12 @@ impl<'a> System<'a> for ProjectileCollisionSystem {
                         );
                     }
 
-                    let _ = system_data.entities.delete(proj);
-                    let between = Uniform::new(-20.0, 20.0);
-                    let mut rng = rand::thread_rng();
-
-                    for _ in 0..4 {
-                        let blood = system_data.entities.create();
-                        system_data
-                            .updater
-                            .insert(blood, PixelParticle::new(BLOOD_COLOR, 10.0));
-                        system_data.updater.insert(blood, *target_pos);
-                        system_data.updater.insert(
-                            blood,
-                            Velocity::new(between.sample(&mut rng), between.sample(&mut rng)),
-                        );
-                    }
+                    Emitter::spawn(
+                        &system_data.entities,
+                        &system_data.updater,
+                        BLOOD,
+                        target_pos.0,
+                    );
 
                     // Play a sound
                     system_data.audio.play_unit_hit();
@@ -237,6 +547,70 @@ impl<'a> System<'a> for ProjectileCollisionSystem {
     }
 }
 
+#[derive(SystemData)]
+pub struct StuckInUnitSystemData<'a> {
+    entities: Entities<'a>,
+    updater: Read<'a, LazyUpdate>,
+    stuck_arrows: Write<'a, StuckArrows>,
+    pos: ReadStorage<'a, WorldPosition>,
+    stuck: ReadStorage<'a, StuckInUnit>,
+    line: WriteStorage<'a, Line>,
+}
+
+pub struct StuckInUnitSystem;
+impl<'a> System<'a> for StuckInUnitSystem {
+    type SystemData = StuckInUnitSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        for (entity, stuck, line) in (
+            &*system_data.entities,
+            &system_data.stuck,
+            &mut system_data.line,
+        )
+            .join()
+        {
+            match system_data.pos.get(stuck.unit) {
+                Some(unit_pos) => {
+                    // Move the arrow along with the unit
+                    let point = |offset: (f64, f64)| {
+                        Point::new(unit_pos.0.x + offset.0, unit_pos.0.y + offset.1).as_usize()
+                    };
+                    line.p1 = point(stuck.offset.0);
+                    line.p2 = point(stuck.offset.1);
+                }
+                None => {
+                    // The unit died, let the arrow drop to the ground as a harmless projectile
+                    let dx = line.p1.x as f64 - line.p2.x as f64;
+                    let dy = line.p1.y as f64 - line.p2.y as f64;
+                    let length = (dx * dx + dy * dy).sqrt();
+
+                    let _ = system_data.entities.delete(entity);
+
+                    let arrow = system_data.entities.create();
+                    system_data.updater.insert(arrow, Projectile);
+                    system_data.updater.insert(arrow, *line);
+                    system_data.updater.insert(arrow, Arrow(length));
+                    system_data.updater.insert(
+                        arrow,
+                        WorldPosition(Point::new(line.p1.x as f64, line.p1.y as f64)),
+                    );
+                    // Keep pointing the way it was stuck, a zero velocity would turn it sideways
+                    let vel = if length > 0.0 {
+                        Velocity::new(dx / length * DROP_SPEED, dy / length * DROP_SPEED)
+                    } else {
+                        Velocity::new(0.0, DROP_SPEED)
+                    };
+                    system_data.updater.insert(arrow, vel);
+                    system_data
+                        .updater
+                        .insert(arrow, Lifetime(PROJECTILE_LIFETIME));
+                    system_data.stuck_arrows.push(&system_data.entities, arrow);
+                }
+            }
+        }
+    }
+}
+
 pub struct ProjectileRemovalFromMaskSystem;
 impl<'a> System<'a> for ProjectileRemovalFromMaskSystem {
     type SystemData = (
@@ -0,0 +1,141 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// Allied units within this distance count as the crew of a battering ram
+const CREW_RADIUS: f64 = 30.0;
+
+/// A siege engine that carves through the walls in its way, it only moves when enough units
+/// push it.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Ram {
+    /// The amount of units needed nearby to move.
+    pub crew: usize,
+    /// The seconds between each hit against a wall.
+    pub delay: f64,
+    /// The hole every hit leaves in the wall.
+    pub mask: MaskId,
+
+    delay_left: f64,
+}
+
+impl Ram {
+    pub fn new(crew: usize, delay: f64, mask: MaskId) -> Self {
+        Ram {
+            crew,
+            delay,
+            mask,
+
+            delay_left: delay,
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct RamSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    terrain: Read<'a, Terrain>,
+    updater: Read<'a, LazyUpdate>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    dest: ReadStorage<'a, Destination>,
+    walk: ReadStorage<'a, Walk>,
+    unit_type: ReadStorage<'a, UnitType>,
+    pos: ReadStorage<'a, WorldPosition>,
+    ram: WriteStorage<'a, Ram>,
+    state: WriteStorage<'a, UnitState>,
+    rect: WriteStorage<'a, Rect>,
+}
+
+pub struct RamSystem;
+impl<'a> System<'a> for RamSystem {
+    type SystemData = RamSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let factions = &system_data.factions;
+        let faction = &system_data.faction;
+
+        // The units that can push a ram, with the faction they are in
+        let crew: Vec<(Point, Option<&Faction>)> = (
+            &*system_data.entities,
+            &system_data.pos,
+            &system_data.unit_type,
+            &system_data.state,
+        )
+            .join()
+            .filter(|(_, _, unit_type, state)| {
+                **unit_type != UnitType::BatteringRam && **state != UnitState::Rout
+            })
+            .map(|(e, pos, _, _)| (pos.0, faction.get(e)))
+            .collect();
+
+        for (e, dest, walk, pos, ram, state, rect) in (
+            &*system_data.entities,
+            &system_data.dest,
+            &system_data.walk,
+            &system_data.pos,
+            &mut system_data.ram,
+            &mut system_data.state,
+            (&mut system_data.rect).maybe(),
+        )
+            .join()
+        {
+            if let Some(rect) = rect {
+                rect.pos = pos.0.as_usize();
+            }
+
+            let ram_faction = faction.get(e);
+            let crewed = crew
+                .iter()
+                .filter(|(crew_pos, crew_faction)| {
+                    factions.allied(ram_faction, *crew_faction)
+                        && pos.0.distance(**crew_pos) < CREW_RADIUS
+                })
+                .count()
+                >= ram.crew;
+
+            let dir = (dest.0 - pos.0.x).signum();
+            match *state {
+                // Rams can't climb, they break through instead
+                UnitState::Climb => *state = UnitState::Breach,
+                UnitState::Breach => {
+                    if ledge_height(&system_data.terrain, walk, pos.0, dir, STEP_HEIGHT).is_some() {
+                        // The way is free again
+                        *state = UnitState::Walk;
+                        continue;
+                    }
+
+                    ram.delay_left -= dt;
+                    if ram.delay_left > 0.0 || !crewed {
+                        continue;
+                    }
+                    ram.delay_left = ram.delay;
+
+                    // Hit the wall in front of the middle of the ram
+                    let front = if dir < 0.0 {
+                        walk.bounds.min.x - 1.0
+                    } else {
+                        walk.bounds.max.x + 1.0
+                    };
+                    let hit = Point::new(
+                        pos.0.x + front,
+                        pos.0.y + (walk.bounds.min.y + walk.bounds.max.y) / 2.0,
+                    );
+                    system_data.updater.insert(
+                        system_data.entities.create(),
+                        TerrainMask::new(ram.mask.id, hit.as_i32(), ram.mask.size),
+                    );
+                    Emitter::spawn(&system_data.entities, &system_data.updater, DUST, hit);
+                }
+                // Wait for the crew
+                UnitState::Walk if !crewed => *state = UnitState::Hold,
+                UnitState::Hold if crewed => *state = UnitState::Walk,
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use cgmath::MetricSpace;
+use collision::Discrete;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// A click selects the units this close to the mouse
+const CLICK_RADIUS: f64 = 2.0;
+
+/// An allied unit selected by the player.
+#[derive(Component, Debug)]
+pub struct Selected;
+
+/// The corner where the player started dragging the selection box.
+#[derive(Debug, Default)]
+pub struct SelectionBox(pub Option<Point>);
+
+/// Start or finish dragging a selection box with the mouse, it's only started when clicking on
+/// the battlefield and not on the gui, a tower slot or a siege engine.
+pub fn update_selection(world: &mut World, on_gui: bool) {
+    let mouse = *world.read_resource::<Mouse>();
+
+    if mouse.left_pressed() {
+        let busy = on_gui
+            || tower_slot_at(world, mouse.pos).is_some()
+            || (
+                &world.read_storage::<PlayerAim>(),
+                &world.read_storage::<WorldPosition>(),
+            )
+                .join()
+                .any(|(aim, pos)| pos.0.distance(*mouse.pos) < aim.grab_radius);
+
+        world.write_resource::<SelectionBox>().0 = if busy { None } else { Some(mouse.pos) };
+    }
+
+    if mouse.left_released() {
+        let start = world.write_resource::<SelectionBox>().0.take();
+        if let Some(start) = start {
+            select_units(world, start, mouse.pos);
+        }
+    }
+}
+
+/// Replace the selection with the allied units inside the box.
+fn select_units(world: &mut World, p1: Point, p2: Point) {
+    let area = BoundingBox::new(
+        Point::new(p1.x.min(p2.x) - CLICK_RADIUS, p1.y.min(p2.y) - CLICK_RADIUS),
+        Point::new(p1.x.max(p2.x) + CLICK_RADIUS, p1.y.max(p2.y) + CLICK_RADIUS),
+    );
+
+    let entities = world.entities();
+    let faction = world.read_storage::<Faction>();
+    let pos = world.read_storage::<WorldPosition>();
+    let bb = world.read_storage::<BoundingBox>();
+    let mut selected = world.write_storage::<Selected>();
+
+    selected.clear();
+    for (unit, faction, pos, bb) in (&*entities, &faction, &pos, &bb).join() {
+        if *faction == Faction::PLAYER && area.intersects(&*(*bb + pos.0)) {
+            let _ = selected.insert(unit, Selected);
+        }
+    }
+}
+
+/// Switch the selected units to the next stance.
+pub fn cycle_stance(world: &mut World) {
+    let selected = world.read_storage::<Selected>();
+    let ranged = world.read_storage::<Ranged>();
+    let shield = world.read_storage::<Shield>();
+    let mut stance = world.write_storage::<Stance>();
+
+    // Give all the units the same stance
+    let next = match (&selected, &stance).join().next() {
+        Some((_, stance)) => stance.next(),
+        None => return,
+    };
+    for (_, ranged, shield, stance) in
+        (&selected, ranged.maybe(), shield.maybe(), &mut stance).join()
+    {
+        // Skip the stances that don't change anything for the unit
+        let mut unit_next = next;
+        while !unit_next.applies(ranged.is_some(), shield.is_some()) {
+            unit_next = unit_next.next();
+        }
+        *stance = unit_next;
+    }
+}
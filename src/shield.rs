@@ -0,0 +1,102 @@
+use rand::{self, Rng};
+use specs::prelude::*;
+use specs_derive::Component;
+use std::f64::consts::PI;
+
+use super::*;
+
+// The steepest angle from the horizon at which a raised shield still catches projectiles
+const SHIELD_ANGLE: f64 = PI / 4.0;
+// A shield wall also covers the units from projectiles falling from above
+const SHIELD_WALL_ANGLE: f64 = PI * 0.45;
+// How much slower than walking a unit moves in a shield wall
+pub const SHIELD_WALL_SPEED_FACTOR: f64 = 0.5;
+
+/// Stops some of the projectiles coming from the direction the unit is facing.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Shield {
+    pub block_chance: f64,
+    /// The chance to block in the shield wall stance.
+    pub wall_block_chance: f64,
+
+    /// -1 when the unit looks to the left and 1 when it looks to the right.
+    facing: f64,
+    wall: bool,
+}
+
+impl Shield {
+    pub fn new(block_chance: f64, wall_block_chance: f64, facing: f64) -> Self {
+        Shield {
+            block_chance,
+            wall_block_chance,
+
+            facing,
+            wall: false,
+        }
+    }
+
+    /// Whether the projectile flying with the velocity bounces off the shield.
+    pub fn blocks(&self, vel: &Velocity) -> bool {
+        // The speed towards the front of the unit
+        let front = -vel.x * self.facing;
+        if front <= 0.0 {
+            // Hit in the back
+            return false;
+        }
+
+        let (max_angle, chance) = if self.wall {
+            (SHIELD_WALL_ANGLE, self.wall_block_chance)
+        } else {
+            (SHIELD_ANGLE, self.block_chance)
+        };
+        if vel.y.abs().atan2(front) > max_angle {
+            return false;
+        }
+
+        rand::thread_rng().gen::<f64>() < chance
+    }
+}
+
+#[derive(SystemData)]
+pub struct ShieldSystemData<'a> {
+    dest: ReadStorage<'a, Destination>,
+    pos: ReadStorage<'a, WorldPosition>,
+    state: ReadStorage<'a, UnitState>,
+    stance: ReadStorage<'a, Stance>,
+    morale: ReadStorage<'a, Morale>,
+    shield: WriteStorage<'a, Shield>,
+}
+
+pub struct ShieldSystem;
+impl<'a> System<'a> for ShieldSystem {
+    type SystemData = ShieldSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        for (dest, pos, state, stance, morale, shield) in (
+            &system_data.dest,
+            &system_data.pos,
+            &system_data.state,
+            system_data.stance.maybe(),
+            system_data.morale.maybe(),
+            &mut system_data.shield,
+        )
+            .join()
+        {
+            if *state == UnitState::Rout {
+                // Fleeing units turn their back to the enemy
+                shield.facing = morale
+                    .map(|morale| morale.flee_dir(pos.0.x))
+                    .filter(|dir| *dir != 0.0)
+                    .unwrap_or(shield.facing);
+                shield.wall = false;
+                continue;
+            }
+
+            // Keep looking the same way when standing at the destination
+            if (dest.0 - pos.0.x).abs() >= 1.0 {
+                shield.facing = (dest.0 - pos.0.x).signum();
+            }
+            shield.wall = stance == Some(&Stance::ShieldWall);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// The amount of points drawn to show where the projectile will fly
+const AIM_PREVIEW_POINTS: usize = 40;
+// The time in seconds between each point of the preview
+const AIM_PREVIEW_STEP: f64 = 0.08;
+
+/// A siege engine the player aims by dragging away from it with the mouse, the projectile is
+/// launched in the opposite direction when the mouse is released.
+#[derive(Component, Debug)]
+pub struct PlayerAim {
+    /// How close to the engine the mouse needs to be to start aiming.
+    pub grab_radius: f64,
+    /// The launch speed for every pixel dragged.
+    pub power: f64,
+    pub max_strength: f64,
+    pub cooldown: f64,
+
+    pub cooldown_left: f64,
+    /// Where the mouse started dragging.
+    pub drag: Option<Point>,
+    /// The predicted flight path while aiming.
+    pub preview: Vec<Point>,
+}
+
+impl PlayerAim {
+    pub fn new(grab_radius: f64, power: f64, max_strength: f64, cooldown: f64) -> Self {
+        PlayerAim {
+            grab_radius,
+            power,
+            max_strength,
+            cooldown,
+
+            cooldown_left: 0.0,
+            drag: None,
+            preview: Vec::new(),
+        }
+    }
+
+    /// The velocity the projectile will be launched with when the mouse is released at the
+    /// position.
+    pub fn velocity(&self, mouse: Point) -> Option<Velocity> {
+        let drag = self.drag?;
+
+        let mut vel = Velocity::new(
+            (drag.x - mouse.x) * self.power,
+            (drag.y - mouse.y) * self.power,
+        );
+
+        let strength = (vel.x * vel.x + vel.y * vel.y).sqrt();
+        if strength > self.max_strength {
+            vel.x *= self.max_strength / strength;
+            vel.y *= self.max_strength / strength;
+        }
+
+        Some(vel)
+    }
+
+    /// How far the engine is reloaded, between 0 and 1.
+    pub fn reload_ratio(&self) -> f64 {
+        1.0 - self.cooldown_left / self.cooldown
+    }
+}
+
+#[derive(SystemData)]
+pub struct PlayerAimSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    grav: Read<'a, Gravity>,
+    mouse: Read<'a, Mouse>,
+    terrain: Read<'a, Terrain>,
+    updater: Read<'a, LazyUpdate>,
+    pos: ReadStorage<'a, WorldPosition>,
+    projectile: ProjectileTemplates<'a>,
+    aim: WriteStorage<'a, PlayerAim>,
+}
+
+pub struct PlayerAimSystem;
+impl<'a> System<'a> for PlayerAimSystem {
+    type SystemData = PlayerAimSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let grav = system_data.grav.0;
+        let mouse = *system_data.mouse;
+
+        for (e, pos, aim) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &mut system_data.aim,
+        )
+            .join()
+        {
+            aim.cooldown_left = (aim.cooldown_left - dt).max(0.0);
+            aim.preview.clear();
+
+            // Start aiming when the mouse is pressed close to the engine
+            if aim.drag.is_none()
+                && mouse.left_pressed()
+                && aim.cooldown_left <= 0.0
+                && pos.0.distance(*mouse.pos) < aim.grab_radius
+            {
+                aim.drag = Some(mouse.pos);
+            }
+
+            let vel = match aim.velocity(mouse.pos) {
+                Some(vel) => vel,
+                None => continue,
+            };
+
+            if !mouse.left {
+                if mouse.left_released() {
+                    // Fire the engine
+                    system_data.projectile.fire(
+                        &system_data.entities,
+                        &system_data.updater,
+                        e,
+                        pos.0,
+                        vel,
+                    );
+
+                    aim.cooldown_left = aim.cooldown;
+                }
+
+                aim.drag = None;
+                continue;
+            }
+
+            // Predict the flight path until it hits the terrain
+            let mut last = pos.0;
+            for i in 1..=AIM_PREVIEW_POINTS {
+                let t = i as f64 * AIM_PREVIEW_STEP;
+                let next = Point::new(
+                    pos.0.x + vel.x * t,
+                    pos.0.y + vel.y * t + 0.5 * grav * t * t,
+                );
+                if system_data
+                    .terrain
+                    .line_collides(last.as_i32(), next.as_i32())
+                    .is_some()
+                {
+                    break;
+                }
+
+                aim.preview.push(next);
+                last = next;
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use line_drawing::Bresenham;
 use specs::*;
 use specs_derive::Component;
+use std::collections::BTreeSet;
 
 use crate::geom::*;
 use crate::physics::*;
@@ -11,6 +12,8 @@ pub struct Terrain {
 
     width: usize,
     height: usize,
+    /// The columns that changed since the last time they were taken.
+    changed: BTreeSet<usize>,
 }
 
 impl Terrain {
@@ -20,6 +23,7 @@ impl Terrain {
 
             width: size.0,
             height: size.1,
+            changed: BTreeSet::new(),
         }
     }
 
@@ -80,6 +84,44 @@ impl Terrain {
         None
     }
 
+    pub fn is_solid(&self, pos: (i32, i32)) -> bool {
+        let (width, height) = self.size();
+        if pos.0 < 0 || pos.1 < 0 || pos.0 as usize >= width || pos.1 as usize >= height {
+            return false;
+        }
+
+        let index = pos.0 as usize + pos.1 as usize * width;
+        (self.buffer[index] & 0xFF_FF_FF) != 0xFF_00_FF
+    }
+
+    /// Find the height of the first solid pixel from the top of the column.
+    pub fn ground_height(&self, x: i32) -> Option<i32> {
+        (0..self.height as i32).find(|y| self.is_solid((x, *y)))
+    }
+
+    /// Approximate the surface normal at a point by averaging the directions pointing away from
+    /// the solid pixels surrounding it.
+    pub fn normal(&self, pos: (i32, i32), radius: i32) -> Option<(f64, f64)> {
+        let mut normal = (0.0, 0.0);
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                if self.is_solid((pos.0 + x, pos.1 + y)) {
+                    normal.0 -= f64::from(x);
+                    normal.1 -= f64::from(y);
+                }
+            }
+        }
+
+        let length = (normal.0 * normal.0 + normal.1 * normal.1).sqrt();
+        if length < f64::EPSILON {
+            // Completely surrounded by terrain or by air
+            return None;
+        }
+
+        Some((normal.0 / length, normal.1 / length))
+    }
+
+    /// Draw a cosmetic pixel, like blood or debris, the columns aren't marked as changed.
     pub fn draw_pixel(&mut self, pos: (usize, usize), color: u32) {
         if pos.0 >= self.width || pos.1 >= self.height {
             return;
@@ -87,18 +129,44 @@ impl Terrain {
 
         self.buffer[pos.0 + pos.1 * self.width] = color;
     }
+
+    /// Remember that the columns between start and end changed.
+    pub fn mark_changed(&mut self, start: usize, end: usize) {
+        self.changed
+            .extend(start.min(self.width)..end.min(self.width));
+    }
+
+    /// The columns that changed since the last call.
+    pub fn take_changed(&mut self) -> BTreeSet<usize> {
+        std::mem::take(&mut self.changed)
+    }
 }
 
 #[derive(Component, Debug)]
 pub struct TerrainMask {
     pub id: usize,
     pub pos: (i32, i32),
+    /// The size of the hole, the size of the mask image times the scale.
     pub size: (usize, usize),
+    /// Every pixel of the mask image becomes a square of this many pixels wide.
+    pub scale: usize,
 }
 
 impl TerrainMask {
     pub fn new(id: usize, pos: (i32, i32), size: (usize, usize)) -> Self {
-        TerrainMask { id, pos, size }
+        TerrainMask {
+            id,
+            pos,
+            size,
+            scale: 1,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.size = (self.size.0 * scale, self.size.1 * scale);
+        self.scale = scale;
+
+        self
     }
 }
 
@@ -0,0 +1,339 @@
+use specs::*;
+use specs_derive::Component;
+
+use crate::*;
+
+const SLOT_COLOR: u32 = 0xFF_84_7E_87;
+const TOWER_WIDTH: usize = 8;
+const TOWER_HEIGHT: usize = 16;
+
+// The part of the spent gold returned when selling a tower
+const SELL_REFUND: f64 = 0.5;
+
+/// The kind of projectile a tower fires.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TowerProjectile {
+    Arrow,
+    Stone,
+    Bomb,
+}
+
+impl TowerProjectile {
+    pub fn damage_type(self) -> DamageType {
+        match self {
+            TowerProjectile::Arrow => DamageType::Pierce,
+            TowerProjectile::Stone => DamageType::Siege,
+            TowerProjectile::Bomb => DamageType::Fire,
+        }
+    }
+}
+
+/// The stats of a tower for an upgrade level.
+#[derive(Debug, Copy, Clone)]
+pub struct TowerTier {
+    pub cost: f64,
+    pub color: u32,
+    pub delay: f64,
+    pub strength: f64,
+    pub damage: f64,
+    pub projectile: TowerProjectile,
+}
+
+pub const TOWER_TIERS: [TowerTier; 3] = [
+    TowerTier {
+        cost: 50.0,
+        color: 0xFF_8F_56_3B,
+        delay: 2.0,
+        strength: 200.0,
+        damage: 8.0,
+        projectile: TowerProjectile::Arrow,
+    },
+    TowerTier {
+        cost: 75.0,
+        color: 0xFF_69_6A_6A,
+        delay: 3.0,
+        strength: 260.0,
+        damage: 25.0,
+        projectile: TowerProjectile::Stone,
+    },
+    TowerTier {
+        cost: 100.0,
+        color: 0xFF_32_3C_39,
+        delay: 4.0,
+        strength: 280.0,
+        damage: 30.0,
+        projectile: TowerProjectile::Bomb,
+    },
+];
+
+/// A place on the ground where the player can build a tower.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct TowerSlot {
+    pub tower: Option<Entity>,
+}
+
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Tower {
+    pub tier: usize,
+    /// The gold spent on building and upgrading the tower.
+    pub spent: f64,
+}
+
+pub fn place_tower_slot(world: &mut World, x: i32) {
+    let ground = world.read_resource::<Terrain>().ground_height(x);
+    if let Some(y) = ground {
+        let pos = Point::new(f64::from(x), f64::from(y - TOWER_HEIGHT as i32));
+
+        world
+            .create_entity()
+            .with(TowerSlot { tower: None })
+            .with(WorldPosition(pos))
+            .with(Rect::new(
+                pos.as_usize(),
+                (TOWER_WIDTH, TOWER_HEIGHT),
+                SLOT_COLOR,
+                false,
+            ))
+            .build();
+    }
+}
+
+/// Find the tower slot at the position.
+pub fn tower_slot_at(world: &World, pos: Point) -> Option<Entity> {
+    let entities = world.entities();
+    let slots = world.read_storage::<TowerSlot>();
+    let positions = world.read_storage::<WorldPosition>();
+
+    (&*entities, &slots, &positions)
+        .join()
+        .find(|(_, _, slot_pos)| {
+            pos.x >= slot_pos.0.x
+                && pos.x < slot_pos.0.x + TOWER_WIDTH as f64
+                && pos.y >= slot_pos.0.y
+                && pos.y < slot_pos.0.y + TOWER_HEIGHT as f64
+        })
+        .map(|(entity, _, _)| entity)
+}
+
+/// Describe what clicking on the slot will do.
+pub fn tower_slot_label(world: &World, slot: Entity) -> Option<String> {
+    let tower = world.read_storage::<TowerSlot>().get(slot)?.tower;
+
+    let next_tier = match tower.and_then(|tower| world.read_storage::<Tower>().get(tower).copied())
+    {
+        Some(tower) => tower.tier + 1,
+        None => 0,
+    };
+
+    Some(match (next_tier, TOWER_TIERS.get(next_tier)) {
+        (0, Some(tier)) => format!("Build {}", tier.cost),
+        (_, Some(tier)) => format!("Upgrade {}", tier.cost),
+        (_, None) => "Max".to_string(),
+    })
+}
+
+/// Build a tower on an empty slot or upgrade the tower that's already there.
+pub fn build_or_upgrade_tower(world: &mut World, slot: Entity) {
+    let (slot_pos, tower) = {
+        let slots = world.read_storage::<TowerSlot>();
+        let positions = world.read_storage::<WorldPosition>();
+
+        match (slots.get(slot), positions.get(slot)) {
+            (Some(slot), Some(pos)) => (pos.0, slot.tower),
+            _ => return,
+        }
+    };
+
+    let (tier, spent) =
+        match tower.and_then(|tower| world.read_storage::<Tower>().get(tower).copied()) {
+            Some(tower) => (tower.tier + 1, tower.spent),
+            None => (0, 0.0),
+        };
+    if tier >= TOWER_TIERS.len() {
+        // Already fully upgraded
+        return;
+    }
+
+    let cost = TOWER_TIERS[tier].cost;
+    if !world.write_resource::<Treasury>().spend(cost) {
+        return;
+    }
+
+    let tower = match tower {
+        Some(tower) => tower,
+        None => {
+            let tower = world
+                .create_entity()
+                .with(Faction::PLAYER)
+                .with(WorldPosition(slot_pos))
+                .with(Point::new(
+                    slot_pos.x + TOWER_WIDTH as f64 / 2.0,
+                    slot_pos.y,
+                ))
+                .build();
+
+            if let Some(slot) = world.write_storage::<TowerSlot>().get_mut(slot) {
+                slot.tower = Some(tower);
+            }
+
+            tower
+        }
+    };
+
+    apply_tower_tier(world, tower, tier, spent + cost);
+}
+
+/// Remove the tower from the slot and refund a part of the gold spent on it.
+pub fn sell_tower(world: &mut World, slot: Entity) {
+    let tower = match world
+        .write_storage::<TowerSlot>()
+        .get_mut(slot)
+        .and_then(|slot| slot.tower.take())
+    {
+        Some(tower) => tower,
+        None => return,
+    };
+
+    let spent = world
+        .read_storage::<Tower>()
+        .get(tower)
+        .map_or(0.0, |tower| tower.spent);
+    world.write_resource::<Treasury>().gold += spent * SELL_REFUND;
+
+    let _ = world.delete_entity(tower);
+}
+
+/// Set the turret and projectile components of the tower for the upgrade level.
+fn apply_tower_tier(world: &mut World, tower: Entity, tier: usize, spent: f64) {
+    let stats = TOWER_TIERS[tier];
+
+    let (projectile1, bighole1) = {
+        let images = &*world.read_resource::<Images>();
+
+        (
+            *images.0.get("projectile1").unwrap(),
+            *images.0.get("bighole1").unwrap(),
+        )
+    };
+
+    let pos = world
+        .read_storage::<WorldPosition>()
+        .get(tower)
+        .map_or(Point::new(0.0, 0.0), |pos| pos.0);
+
+    world
+        .write_storage::<Tower>()
+        .insert(tower, Tower { tier, spent })
+        .unwrap();
+    world
+        .write_storage::<Rect>()
+        .insert(
+            tower,
+            Rect::new(
+                pos.as_usize(),
+                (TOWER_WIDTH, TOWER_HEIGHT),
+                stats.color,
+                true,
+            ),
+        )
+        .unwrap();
+
+    // Keep the reload time of the old turret so upgrading doesn't make it fire immediately
+    let delay_left = world
+        .read_storage::<Turret>()
+        .get(tower)
+        .map_or(0.0, |turret| turret.delay_left);
+    let trajectory = if stats.projectile == TowerProjectile::Arrow {
+        Trajectory::Low
+    } else {
+        Trajectory::High
+    };
+    world
+        .write_storage::<Turret>()
+        .insert(
+            tower,
+            Turret {
+                delay: stats.delay,
+                min_distance: 30.0,
+                strength: stats.strength,
+                trajectory,
+                accuracy: 0.9,
+                delay_left,
+                ..Turret::default()
+            },
+        )
+        .unwrap();
+    world
+        .write_storage::<Damage>()
+        .insert(tower, Damage(stats.damage, stats.projectile.damage_type()))
+        .unwrap();
+
+    // Replace the projectile of the previous tier
+    world.write_storage::<Arrow>().remove(tower);
+    world.write_storage::<Line>().remove(tower);
+    world.write_storage::<ProjectileSprite>().remove(tower);
+    world.write_storage::<MaskId>().remove(tower);
+    world.write_storage::<Explosive>().remove(tower);
+
+    match stats.projectile {
+        TowerProjectile::Arrow => {
+            world
+                .write_storage::<Arrow>()
+                .insert(tower, Arrow(5.0))
+                .unwrap();
+            world
+                .write_storage::<Line>()
+                .insert(tower, Line::new(WOOD_COLOR))
+                .unwrap();
+            world
+                .write_storage::<ProjectileBoundingBox>()
+                .insert(
+                    tower,
+                    ProjectileBoundingBox(BoundingBox::new(
+                        Point::new(0.0, 0.0),
+                        Point::new(1.0, 1.0),
+                    )),
+                )
+                .unwrap();
+        }
+        TowerProjectile::Stone | TowerProjectile::Bomb => {
+            world
+                .write_storage::<ProjectileSprite>()
+                .insert(tower, ProjectileSprite(Sprite::new(projectile1)))
+                .unwrap();
+            world
+                .write_storage::<MaskId>()
+                .insert(
+                    tower,
+                    MaskId {
+                        id: bighole1,
+                        size: (5, 5),
+                    },
+                )
+                .unwrap();
+            world
+                .write_storage::<ProjectileBoundingBox>()
+                .insert(
+                    tower,
+                    ProjectileBoundingBox(BoundingBox::new(
+                        Point::new(0.0, 0.0),
+                        Point::new(5.0, 5.0),
+                    )),
+                )
+                .unwrap();
+
+            if stats.projectile == TowerProjectile::Bomb {
+                world
+                    .write_storage::<Explosive>()
+                    .insert(
+                        tower,
+                        Explosive {
+                            radius: 20.0,
+                            knockback: 80.0,
+                        },
+                    )
+                    .unwrap();
+            }
+        }
+    }
+}
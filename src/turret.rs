@@ -3,16 +3,90 @@ use rand;
 use rand::distributions::{Distribution, Uniform};
 use specs::prelude::*;
 use specs_derive::Component;
+use std::cmp::Ordering;
 
 use super::*;
 
+// The amount of line segments the flight path is split in when checking for terrain
+const PATH_SEGMENTS: usize = 16;
+// A flight path is clear when it only hits the terrain this close to the target
+const PATH_CLEAR_RADIUS: f64 = 8.0;
+
+/// Which of the two possible arcs to a target is preferred.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Trajectory {
+    Low,
+    High,
+}
+
+impl Trajectory {
+    pub fn other(self) -> Self {
+        match self {
+            Trajectory::Low => Trajectory::High,
+            Trajectory::High => Trajectory::Low,
+        }
+    }
+}
+
+/// Which unit the turret prefers to shoot at.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Targeting {
+    Closest,
+    Furthest,
+    Weakest,
+    Strongest,
+    /// The unit closest to its destination.
+    First,
+    /// Units of a type, or the closest unit when there are none.
+    Prefer(UnitType),
+}
+
+impl Targeting {
+    /// Sort the targets so the most preferred one comes first.
+    fn sort(self, from: Point, targets: &mut [Target]) {
+        let dist = |target: &Target| from.distance(*target.pos);
+
+        targets.sort_by(|a, b| {
+            let order = match self {
+                Targeting::Closest => dist(a).partial_cmp(&dist(b)),
+                Targeting::Furthest => dist(b).partial_cmp(&dist(a)),
+                Targeting::Weakest => a.health.partial_cmp(&b.health),
+                Targeting::Strongest => b.health.partial_cmp(&a.health),
+                Targeting::First => a.dest_dist.partial_cmp(&b.dest_dist),
+                Targeting::Prefer(unit_type) => {
+                    let is_a = a.unit_type == Some(unit_type);
+                    let is_b = b.unit_type == Some(unit_type);
+
+                    Some(is_b.cmp(&is_a))
+                }
+            }
+            .unwrap_or(Ordering::Equal);
+
+            // When both are equally preferred take the closest
+            order.then_with(|| dist(a).partial_cmp(&dist(b)).unwrap_or(Ordering::Equal))
+        });
+    }
+}
+
+/// A unit that can be shot at.
+struct Target {
+    pos: Point,
+    speed: f64,
+    health: f64,
+    dest_dist: f64,
+    unit_type: Option<UnitType>,
+}
+
 #[derive(Component, Debug)]
 pub struct Turret {
     pub delay: f64,
     pub min_distance: f64,
-    pub max_strength: f64,
-    pub flight_time: f64,
-    pub strength_variation: f64,
+    /// The speed at which projectiles are launched.
+    pub strength: f64,
+    pub trajectory: Trajectory,
+    /// How well the turret aims between 0 and 1, where 1 always hits.
+    pub accuracy: f64,
+    pub targeting: Targeting,
 
     pub delay_left: f64,
 }
@@ -22,20 +96,152 @@ impl Default for Turret {
         Turret {
             delay: 5.0,
             min_distance: 20.0,
-            max_strength: 210.0,
-            flight_time: 3.0,
-            strength_variation: 0.1,
+            strength: 210.0,
+            trajectory: Trajectory::Low,
+            accuracy: 0.9,
+            targeting: Targeting::Closest,
 
             delay_left: 0.0,
         }
     }
 }
 
+/// The horizontal speed a unit is moving with, units that are waiting or fighting only move when
+/// they are knocked back. The flee direction is where retreating or routing units move to.
+pub fn unit_speed(
+    state: &UnitState,
+    speed: f64,
+    pos: &WorldPosition,
+    dest: Option<&Destination>,
+    route: Option<&Route>,
+    flee: f64,
+    vel: Option<&Velocity>,
+) -> f64 {
+    let knockback = vel.map_or(0.0, |vel| vel.x);
+
+    match (state, dest) {
+        (UnitState::Walk, Some(dest)) => speed * walk_dir(route, dest, pos) + knockback,
+        (UnitState::Rout, _) => speed * flee + knockback,
+        (UnitState::Retreat, _) => speed * RETREAT_SPEED_FACTOR * flee + knockback,
+        _ => knockback,
+    }
+}
+
+/// The direction a retreating unit backs away in or a routing unit flees to.
+fn flee_dir(state: &UnitState, x: f64, ranged: Option<&Ranged>, morale: Option<&Morale>) -> f64 {
+    if *state == UnitState::Rout {
+        morale.map_or(0.0, |morale| morale.flee_dir(x))
+    } else {
+        ranged.map_or(0.0, |ranged| ranged.away)
+    }
+}
+
+/// Calculate the velocity to hit the target when launching with the speed, returns the velocity
+/// and the time it takes to hit the target.
+pub fn solve_ballistic(
+    from: Point,
+    to: Point,
+    speed: f64,
+    grav: f64,
+    trajectory: Trajectory,
+) -> Option<(Velocity, f64)> {
+    let dx = to.x - from.x;
+    // The y axis points down so flip it
+    let dy = from.y - to.y;
+    if dx.abs() < 1.0 {
+        return None;
+    }
+
+    let speed2 = speed * speed;
+    let discriminant = speed2 * speed2 - grav * (grav * dx * dx + 2.0 * dy * speed2);
+    if discriminant < 0.0 {
+        // The target is out of reach
+        return None;
+    }
+
+    let root = match trajectory {
+        Trajectory::Low => speed2 - discriminant.sqrt(),
+        Trajectory::High => speed2 + discriminant.sqrt(),
+    };
+    let angle = (root / (grav * dx.abs())).atan();
+
+    let vx = speed * angle.cos();
+    let vy = -speed * angle.sin();
+
+    Some((Velocity::new(vx * dx.signum(), vy), dx.abs() / vx))
+}
+
+/// Check if a projectile launched with the velocity doesn't hit the terrain before it arrives at
+/// the target.
+pub fn path_is_clear(
+    terrain: &Terrain,
+    from: Point,
+    to: Point,
+    vel: Velocity,
+    time: f64,
+    grav: f64,
+) -> bool {
+    let point_at = |t: f64| Point::new(from.x + vel.x * t, from.y + vel.y * t + 0.5 * grav * t * t);
+
+    let mut last = from;
+    for i in 1..=PATH_SEGMENTS {
+        let next = point_at(time * i as f64 / PATH_SEGMENTS as f64);
+        if let Some(hit) = terrain.line_collides(last.as_i32(), next.as_i32()) {
+            let hit = Point::new(f64::from(hit.0), f64::from(hit.1));
+
+            return hit.distance(*to) < PATH_CLEAR_RADIUS;
+        }
+        last = next;
+    }
+
+    true
+}
+
 #[derive(Component, Debug, Copy, Clone)]
 pub struct TurretOffset(pub (f64, f64));
 
+/// A unit with a turret that keeps its distance from enemy units instead of walking into them.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Ranged {
+    /// The unit stops walking when an enemy is closer than this.
+    pub engage_distance: f64,
+    /// The unit backs away when an enemy is closer than this.
+    pub kite_distance: f64,
+
+    /// The direction away from the closest enemy.
+    pub away: f64,
+}
+
+impl Ranged {
+    pub fn new(engage_distance: f64, kite_distance: f64) -> Self {
+        Ranged {
+            engage_distance,
+            kite_distance,
+
+            away: 0.0,
+        }
+    }
+
+    /// The state the unit should be in with the closest enemy at the distance.
+    fn state(self, distance: f64) -> UnitState {
+        if distance < self.kite_distance {
+            UnitState::Retreat
+        } else if distance < self.engage_distance {
+            UnitState::Hold
+        } else {
+            UnitState::Walk
+        }
+    }
+}
+
 #[derive(SystemData)]
 pub struct TurretUnitSystemData<'a> {
+    entities: Entities<'a>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
+    walk: ReadStorage<'a, Walk>,
+    ranged: WriteStorage<'a, Ranged>,
+    stance: ReadStorage<'a, Stance>,
     turret: ReadStorage<'a, Turret>,
     wpos: ReadStorage<'a, WorldPosition>,
     offset: ReadStorage<'a, TurretOffset>,
@@ -48,7 +254,11 @@ impl<'a> System<'a> for TurretUnitSystem {
     type SystemData = TurretUnitSystemData<'a>;
 
     fn run(&mut self, mut system_data: Self::SystemData) {
-        for (turret, wpos, offset, state, pos) in (
+        let factions = &system_data.factions;
+        let faction = &system_data.faction;
+
+        for (e, turret, wpos, offset, state, pos) in (
+            &*system_data.entities,
             &system_data.turret,
             &system_data.wpos,
             &system_data.offset,
@@ -60,14 +270,58 @@ impl<'a> System<'a> for TurretUnitSystem {
             pos.0.x = wpos.0.x + (offset.0).0;
             pos.0.y = wpos.0.y + (offset.0).1;
 
-            let unit_stop_moving_offset = turret.delay / 4.0;
-            if turret.delay_left > unit_stop_moving_offset {
-                // Set the state if the unit turret just shot
-                *state = UnitState::Shoot;
-            } else if turret.delay_left < unit_stop_moving_offset && *state == UnitState::Shoot {
-                // Make the unit walk again when the timeout is over
-                *state = UnitState::Walk;
+            let stance = system_data.stance.get(e).copied();
+            let ranged = match system_data.ranged.get_mut(e) {
+                // Aggressive units don't keep their distance
+                Some(ranged) if stance != Some(Stance::Aggressive) => ranged,
+                _ => {
+                    if *state == UnitState::Hold || *state == UnitState::Retreat {
+                        // The stance changed while keeping distance
+                        *state = UnitState::Walk;
+                    }
+
+                    // Units without a preferred distance only stop for a moment when they shoot
+                    let unit_stop_moving_offset = turret.delay / 4.0;
+                    if turret.delay_left > unit_stop_moving_offset && *state == UnitState::Walk {
+                        *state = UnitState::Shoot;
+                    } else if turret.delay_left < unit_stop_moving_offset
+                        && *state == UnitState::Shoot
+                    {
+                        *state = UnitState::Walk;
+                    }
+                    continue;
+                }
+            };
+
+            // Fighting and climbing units can't decide to keep their distance
+            match *state {
+                UnitState::Walk | UnitState::Wait | UnitState::Hold | UnitState::Retreat => (),
+                _ => continue,
+            }
+
+            // Find the distance to the closest hostile unit
+            let closest = (&*system_data.entities, &system_data.wpos, &system_data.walk)
+                .join()
+                .filter(|(unit, _, _)| factions.hostile(faction.get(e), faction.get(*unit)))
+                .map(|(_, upos, _)| (wpos.0.distance(*upos.0), upos.0.x))
+                .fold((f64::MAX, wpos.0.x), |closest, unit| {
+                    if unit.0 < closest.0 {
+                        unit
+                    } else {
+                        closest
+                    }
+                });
+
+            ranged.away = (wpos.0.x - closest.1).signum();
+            let mut new_state = ranged.state(closest.0);
+            if new_state == UnitState::Retreat && stance == Some(Stance::StandGround) {
+                new_state = UnitState::Hold;
             }
+            if new_state == UnitState::Walk && *state == UnitState::Wait {
+                // Let the collision decide when waiting units can walk again
+                continue;
+            }
+            *state = new_state;
         }
     }
 }
@@ -77,19 +331,24 @@ pub struct TurretSystemData<'a> {
     entities: Entities<'a>,
     dt: Read<'a, DeltaTime>,
     grav: Read<'a, Gravity>,
-    ally: ReadStorage<'a, Ally>,
-    enemy: ReadStorage<'a, Enemy>,
+    terrain: Read<'a, Terrain>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
     pos: ReadStorage<'a, Point>,
     wpos: ReadStorage<'a, WorldPosition>,
-    sprite: ReadStorage<'a, ProjectileSprite>,
-    arrow: ReadStorage<'a, Arrow>,
-    line: ReadStorage<'a, Line>,
-    mask: ReadStorage<'a, MaskId>,
-    ignore: ReadStorage<'a, IgnoreCollision>,
-    bb: ReadStorage<'a, ProjectileBoundingBox>,
+    projectile: ProjectileTemplates<'a>,
     ubb: ReadStorage<'a, BoundingBox>,
-    dmg: ReadStorage<'a, Damage>,
     walk: ReadStorage<'a, Walk>,
+    dest: ReadStorage<'a, Destination>,
+    route: ReadStorage<'a, Route>,
+    ranged: ReadStorage<'a, Ranged>,
+    morale: ReadStorage<'a, Morale>,
+    charge: ReadStorage<'a, Charge>,
+    buffs: ReadStorage<'a, Buffs>,
+    stance: ReadStorage<'a, Stance>,
+    vel: ReadStorage<'a, Velocity>,
+    health: ReadStorage<'a, Health>,
+    unit_type: ReadStorage<'a, UnitType>,
     state: ReadStorage<'a, UnitState>,
     turret: WriteStorage<'a, Turret>,
     updater: Read<'a, LazyUpdate>,
@@ -102,12 +361,11 @@ impl<'a> System<'a> for TurretSystem {
     fn run(&mut self, mut system_data: Self::SystemData) {
         let dt = system_data.dt.to_seconds();
         let grav = system_data.grav.0;
+        let terrain = &system_data.terrain;
 
-        for (e, tpos, bb, dmg, turret) in (
+        for (e, tpos, turret) in (
             &*system_data.entities,
             &system_data.pos,
-            &system_data.bb,
-            &system_data.dmg,
             &mut system_data.turret,
         )
             .join()
@@ -117,109 +375,106 @@ impl<'a> System<'a> for TurretSystem {
                 continue;
             }
 
-            // Find the nearest ally to shoot
-            let mut closest = Point::new(-1000.0, -1000.0);
-            let mut dist = tpos.distance(*closest);
-
-            let is_ally: Option<&Ally> = system_data.ally.get(e);
-            if is_ally.is_some() {
-                for (epos, _, walk, ubb, state) in (
-                    &system_data.wpos,
-                    &system_data.enemy,
-                    &system_data.walk,
-                    &system_data.ubb,
-                    &system_data.state,
-                )
-                    .join()
-                {
-                    let mut pos = epos.0;
-                    pos.x += ubb.width() / 2.0;
-                    pos.y += ubb.height() / 2.0;
-
-                    if *state == UnitState::Walk {
-                        pos.x += walk.speed * turret.flight_time;
-                    }
+            // Routing units don't shoot
+            if system_data.state.get(e) == Some(&UnitState::Rout) {
+                continue;
+            }
 
-                    let dist_to = tpos.distance(*pos);
-                    if dist_to < dist && dist_to > turret.min_distance {
-                        dist = dist_to;
-                        closest = pos;
-                    }
-                }
-            } else {
-                for (apos, _, walk, ubb, state) in (
-                    &system_data.wpos,
-                    &system_data.ally,
-                    &system_data.walk,
-                    &system_data.ubb,
-                    &system_data.state,
-                )
-                    .join()
+            // Find all the hostile units that can be shot at, with the speed they are walking with
+            let mut targets = Vec::new();
+            let faction = system_data.faction.get(e);
+            for (unit, upos, walk, ubb, state) in (
+                &*system_data.entities,
+                &system_data.wpos,
+                &system_data.walk,
+                &system_data.ubb,
+                &system_data.state,
+            )
+                .join()
+            {
+                if !system_data
+                    .factions
+                    .hostile(faction, system_data.faction.get(unit))
                 {
-                    let mut pos = apos.0;
-                    pos.x += ubb.width() / 2.0;
-                    pos.y += ubb.height() / 2.0;
+                    continue;
+                }
 
-                    if *state == UnitState::Walk {
-                        pos.x += walk.speed * turret.flight_time;
-                    }
+                let mut pos = upos.0;
+                pos.x += ubb.width() / 2.0;
+                pos.y += ubb.height() / 2.0;
 
-                    let dist_to = tpos.distance(*pos);
-                    if dist_to < dist && dist_to > turret.min_distance {
-                        dist = dist_to;
-                        closest = pos;
-                    }
-                }
+                let speed = unit_speed(
+                    state,
+                    effective_speed(
+                        walk,
+                        system_data.charge.get(unit),
+                        system_data.buffs.get(unit),
+                        system_data.stance.get(unit),
+                    ),
+                    upos,
+                    system_data.dest.get(unit),
+                    system_data.route.get(unit),
+                    flee_dir(
+                        state,
+                        upos.0.x,
+                        system_data.ranged.get(unit),
+                        system_data.morale.get(unit),
+                    ),
+                    system_data.vel.get(unit),
+                );
+
+                targets.push(Target {
+                    pos,
+                    speed,
+                    health: system_data.health.get(unit).map_or(0.0, |health| health.0),
+                    dest_dist: system_data
+                        .dest
+                        .get(unit)
+                        .map_or(f64::MAX, |dest| (dest.0 - upos.0.x).abs()),
+                    unit_type: system_data.unit_type.get(unit).copied(),
+                });
             }
 
-            let variation = if turret.strength_variation > 0.0 {
-                let between = if closest.x > tpos.x {
-                    Uniform::new(0.0, turret.strength_variation)
-                } else {
-                    Uniform::new(-turret.strength_variation, 0.0)
-                };
+            targets.retain(|target| tpos.distance(*target.pos) > turret.min_distance);
+            turret.targeting.sort(*tpos, &mut targets);
 
-                between.sample(&mut rand::thread_rng()) * dist
-            } else {
-                1.0
-            };
+            // Find the first target with an arc that isn't blocked by the terrain
+            let spread = Uniform::new_inclusive(-1.0, 1.0);
+            let mut rng = rand::thread_rng();
+            let aim = targets.iter().find_map(|target| {
+                // Miss the target by a random amount depending on the accuracy
+                let error =
+                    spread.sample(&mut rng) * (1.0 - turret.accuracy) * tpos.distance(*target.pos);
+
+                [turret.trajectory, turret.trajectory.other()]
+                    .iter()
+                    .find_map(|trajectory| {
+                        // Aim at where the target will be when the projectile arrives
+                        let (_, time) =
+                            solve_ballistic(*tpos, target.pos, turret.strength, grav, *trajectory)?;
+                        let mut predicted = target.pos;
+                        predicted.x += target.speed * time + error;
+
+                        let (vel, time) =
+                            solve_ballistic(*tpos, predicted, turret.strength, grav, *trajectory)?;
 
-            let time = turret.flight_time;
-            let vx = (closest.x - tpos.x + variation) / time;
-            let vy = (closest.y + 0.5 * -grav * time * time - tpos.y) / time;
+                        if path_is_clear(terrain, *tpos, predicted, vel, time, grav) {
+                            Some(vel)
+                        } else {
+                            None
+                        }
+                    })
+            });
 
-            if (vx * vx + vy * vy).sqrt() < turret.max_strength {
+            if let Some(vel) = aim {
                 // Shoot the turret
-                let projectile = system_data.entities.create();
-                system_data.updater.insert(projectile, Projectile);
-                system_data
-                    .updater
-                    .insert(projectile, WorldPosition(Point::new(tpos.x, tpos.y)));
-                system_data
-                    .updater
-                    .insert(projectile, Velocity::new(vx, vy));
-                system_data.updater.insert(projectile, *bb);
-                system_data.updater.insert(projectile, *dmg);
-                let entity: Option<&MaskId> = system_data.mask.get(e);
-                if let Some(mask_e) = entity {
-                    system_data.updater.insert(projectile, *mask_e);
-                }
-                let entity: Option<&ProjectileSprite> = system_data.sprite.get(e);
-                if let Some(sprite_e) = entity {
-                    system_data.updater.insert(projectile, sprite_e.0);
-                }
-                let entity: Option<&Arrow> = system_data.arrow.get(e);
-                if let Some(arrow_e) = entity {
-                    system_data.updater.insert(projectile, *arrow_e);
-                }
-                let entity: Option<&Line> = system_data.line.get(e);
-                if let Some(line_e) = entity {
-                    system_data.updater.insert(projectile, *line_e);
-                }
-                let entity: Option<&IgnoreCollision> = system_data.ignore.get(e);
-                if let Some(ignore_e) = entity {
-                    system_data.updater.insert(projectile, *ignore_e);
-                }
+                system_data.projectile.fire(
+                    &system_data.entities,
+                    &system_data.updater,
+                    e,
+                    *tpos,
+                    vel,
+                );
 
                 turret.delay_left = turret.delay;
             }
@@ -5,6 +5,15 @@ use specs_derive::Component;
 
 use super::*;
 
+// How fast the horizontal velocity is lost per second when a unit is on the ground
+const GROUND_FRICTION: f64 = 6.0;
+// Falling higher than this amount of pixels will damage the unit
+pub const FALL_DAMAGE_HEIGHT: f64 = 30.0;
+// The damage for every pixel fallen above the height
+const FALL_DAMAGE_PER_PIXEL: f64 = 1.0;
+// How much slower than walking a unit moves when backing away from enemies
+pub const RETREAT_SPEED_FACTOR: f64 = 0.5;
+
 #[derive(Component, Debug, Eq, PartialEq)]
 pub enum UnitState {
     // The path is clear and the unit can walk
@@ -18,11 +27,121 @@ pub enum UnitState {
     Melee,
     // The unit is shooting at an enemy unit
     Shoot,
+    // The unit has an enemy in range and stays where it is to shoot at it
+    Hold,
+    // An enemy is too close and the unit backs away from it
+    Retreat,
+    // The morale of the unit broke and it flees to its own side
+    Rout,
+    // A siege engine is battering the wall in front of it
+    Breach,
+}
+
+/// The kind of unit, used to decide what to attack.
+#[derive(Component, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UnitType {
+    Soldier,
+    Archer,
+    /// A melee unit that blocks projectiles with its shield.
+    ShieldBearer,
+    /// Heals the wounded units around it.
+    Medic,
+    /// Makes the units around it hit harder and walk faster.
+    BannerBearer,
+    /// Raises the morale of the units around it.
+    Drummer,
+    /// A slow siege engine that breaks through walls.
+    BatteringRam,
+    /// A fast unit that builds up speed and charges into the enemy.
+    Cavalry,
+}
+
+impl UnitType {
+    /// The gold needed to buy the unit.
+    pub fn cost(self) -> f64 {
+        match self {
+            UnitType::Soldier => 15.0,
+            UnitType::Archer => 20.0,
+            UnitType::ShieldBearer => 25.0,
+            UnitType::Medic => 25.0,
+            UnitType::BannerBearer => 30.0,
+            UnitType::Drummer => 20.0,
+            UnitType::BatteringRam => 60.0,
+            UnitType::Cavalry => 40.0,
+        }
+    }
+
+    /// Whether a charging unit can trample the unit and ride on.
+    pub fn is_light(self) -> bool {
+        match self {
+            UnitType::ShieldBearer | UnitType::BatteringRam | UnitType::Cavalry => false,
+            UnitType::Soldier
+            | UnitType::Archer
+            | UnitType::Medic
+            | UnitType::BannerBearer
+            | UnitType::Drummer => true,
+        }
+    }
+}
+
+/// How a unit behaves when it meets enemies.
+#[derive(Component, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stance {
+    /// Ranged units keep their distance and back away from enemies that come close.
+    Skirmish,
+    /// Keep walking towards the destination and fight everything in the way.
+    Aggressive,
+    /// Ranged units keep their distance but never back away.
+    StandGround,
+    /// Units with shields move slowly behind them and block much more.
+    ShieldWall,
+}
+
+impl Stance {
+    /// The stance that comes after this one when switching.
+    pub fn next(self) -> Self {
+        match self {
+            Stance::Skirmish => Stance::Aggressive,
+            Stance::Aggressive => Stance::StandGround,
+            Stance::StandGround => Stance::ShieldWall,
+            Stance::ShieldWall => Stance::Skirmish,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stance::Skirmish => "Skirmish",
+            Stance::Aggressive => "Aggressive",
+            Stance::StandGround => "Stand ground",
+            Stance::ShieldWall => "Shield wall",
+        }
+    }
+
+    /// Whether the stance changes anything for a unit with or without a bow and a shield.
+    pub fn applies(self, ranged: bool, shield: bool) -> bool {
+        match self {
+            Stance::Skirmish | Stance::StandGround => ranged,
+            Stance::Aggressive => true,
+            Stance::ShieldWall => shield,
+        }
+    }
+
+    /// How much faster or slower than normal the unit walks.
+    pub fn speed_factor(self) -> f64 {
+        match self {
+            Stance::ShieldWall => SHIELD_WALL_SPEED_FACTOR,
+            _ => 1.0,
+        }
+    }
 }
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Health(pub f64);
 
+/// The health a unit starts with, it can't be healed above it.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct MaxHealth(pub f64);
+
 #[derive(Component, Debug, Copy, Clone)]
 pub struct HealthBar {
     pub health: f64,
@@ -30,17 +149,25 @@ pub struct HealthBar {
     pub width: usize,
     pub pos: Point2<usize>,
     pub offset: (i32, i32),
+    /// The health went up since the last frame.
+    pub healing: bool,
 }
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Walk {
     pub bounds: BoundingBox,
     pub speed: f64,
+    /// Whether the unit landed on the ground in the last fall step.
+    pub grounded: bool,
 }
 
 impl Walk {
     pub fn new(bounds: BoundingBox, speed: f64) -> Self {
-        Walk { bounds, speed }
+        Walk {
+            bounds,
+            speed,
+            grounded: false,
+        }
     }
 }
 
@@ -50,7 +177,14 @@ pub struct WalkSystemData<'a> {
     terrain: Read<'a, Terrain>,
     dest: ReadStorage<'a, Destination>,
     walk: ReadStorage<'a, Walk>,
+    route: ReadStorage<'a, Route>,
+    ranged: ReadStorage<'a, Ranged>,
+    morale: ReadStorage<'a, Morale>,
+    charge: ReadStorage<'a, Charge>,
+    buffs: ReadStorage<'a, Buffs>,
+    stance: ReadStorage<'a, Stance>,
     state: WriteStorage<'a, UnitState>,
+    vel: WriteStorage<'a, Velocity>,
     pos: WriteStorage<'a, WorldPosition>,
 }
 
@@ -61,29 +195,75 @@ impl<'a> System<'a> for WalkSystem {
     fn run(&mut self, mut system_data: Self::SystemData) {
         let dt = system_data.dt.to_seconds();
 
-        for (dest, walk, state, pos) in (
+        for (dest, walk, route, ranged, morale, charge, buffs, stance, state, vel, pos) in (
             &system_data.dest,
             &system_data.walk,
+            system_data.route.maybe(),
+            system_data.ranged.maybe(),
+            system_data.morale.maybe(),
+            system_data.charge.maybe(),
+            system_data.buffs.maybe(),
+            system_data.stance.maybe(),
             &mut system_data.state,
+            (&mut system_data.vel).maybe(),
             &mut system_data.pos,
         )
             .join()
         {
+            let speed = effective_speed(walk, charge, buffs, stance);
+
+            if *state == UnitState::Retreat {
+                // Back away from the enemy but don't walk into walls
+                let dir = ranged.map_or(-(dest.0 - pos.0.x).signum(), |ranged| ranged.away);
+                let old_x = pos.0.x;
+                pos.0.x += speed * RETREAT_SPEED_FACTOR * dt * dir;
+                if system_data
+                    .terrain
+                    .rect_collides(walk.bounds + *pos.0)
+                    .is_some()
+                {
+                    pos.0.x = old_x;
+                    *state = UnitState::Hold;
+                }
+                continue;
+            }
+
+            if *state == UnitState::Rout {
+                // Flee to the own side, cowering in front of walls
+                if let Some(morale) = morale {
+                    let dir = morale.flee_dir(pos.0.x);
+                    if ledge_height(&system_data.terrain, walk, pos.0, dir, STEP_HEIGHT).is_some() {
+                        pos.0.x += speed * dt * dir;
+                    }
+                }
+                continue;
+            }
+
             // Don't walk when the unitstate is not saying that it can walk
             if *state != UnitState::Walk {
                 continue;
             }
 
-            let hit_box = walk.bounds + *pos.0;
-            if let Some(hit) = system_data.terrain.rect_collides(hit_box) {
-                if hit.1 == hit_box.min.y as i32 {
-                    // Top edge of bounding box is hit, try to climb
-                    *state = UnitState::Climb;
-                    continue;
+            let dir = walk_dir(route, dest, pos);
+            if dir == 0.0 {
+                // There is no way forward, hold
+                continue;
+            }
+
+            // Jump over the gap in front when standing on the ground
+            if let (Some(route), Some(vel)) = (route, vel) {
+                if route.jump && walk.grounded {
+                    vel.y = -JUMP_SPEED;
                 }
             }
 
-            pos.0.x += walk.speed * dt * (dest.0 - pos.0.x).signum();
+            if ledge_height(&system_data.terrain, walk, pos.0, dir, STEP_HEIGHT).is_none() {
+                // The ledge in front is too high to walk over, try to climb
+                *state = UnitState::Climb;
+                continue;
+            }
+
+            pos.0.x += speed * dt * dir;
         }
     }
 }
@@ -98,6 +278,7 @@ impl<'a> System<'a> for HealthBarSystem {
 
     fn run(&mut self, (health, pos, mut health_bar): Self::SystemData) {
         for (health, pos, health_bar) in (&health, &pos, &mut health_bar).join() {
+            health_bar.healing = health.0 > health_bar.health;
             health_bar.health = health.0;
             health_bar.pos = pos.0.as_usize();
             health_bar.pos.x = (health_bar.pos.x as i32 + health_bar.offset.0) as usize;
@@ -106,31 +287,127 @@ impl<'a> System<'a> for HealthBarSystem {
     }
 }
 
+#[derive(SystemData)]
+pub struct UnitFallSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    grav: Read<'a, Gravity>,
+    terrain: Read<'a, Terrain>,
+    updater: Read<'a, LazyUpdate>,
+    armor: ReadStorage<'a, Armor>,
+    walk: WriteStorage<'a, Walk>,
+    vel: WriteStorage<'a, Velocity>,
+    health: WriteStorage<'a, Health>,
+    pos: WriteStorage<'a, WorldPosition>,
+}
+
 pub struct UnitFallSystem;
 impl<'a> System<'a> for UnitFallSystem {
-    type SystemData = (
-        Read<'a, DeltaTime>,
-        Read<'a, Terrain>,
-        ReadStorage<'a, Walk>,
-        WriteStorage<'a, WorldPosition>,
-    );
+    type SystemData = UnitFallSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let grav = system_data.grav.0;
+        let dt = system_data.dt.to_seconds();
+        let height = system_data.terrain.size().1 as f64;
 
-    fn run(&mut self, (dt, terrain, walk, mut pos): Self::SystemData) {
-        let dt = dt.to_seconds();
+        for (entity, walk, vel, health, pos) in (
+            &*system_data.entities,
+            &mut system_data.walk,
+            &mut system_data.vel,
+            &mut system_data.health,
+            &mut system_data.pos,
+        )
+            .join()
+        {
+            // Move sideways when the unit is knocked back but don't push it into a wall
+            let old_x = pos.0.x;
+            pos.0.x += vel.x * dt;
+            if system_data
+                .terrain
+                .rect_collides(walk.bounds + *pos.0)
+                .is_some()
+            {
+                pos.0.x = old_x;
+                vel.x = 0.0;
+            }
 
-        for (walk, pos) in (&walk, &mut pos).join() {
-            pos.0.y += GRAVITY * dt;
+            vel.y += grav * dt;
+            pos.0.y += vel.y * dt;
+            walk.grounded = false;
+
+            // The unit fell off the bottom of the map
+            if pos.0.y > height {
+                let _ = system_data.entities.delete(entity);
+
+                // Show the death at the bottom of the screen where the unit fell out
+                system_data.updater.insert(
+                    system_data.entities.create(),
+                    FloatingText {
+                        text: "x".to_string(),
+                        pos: Point::new(pos.0.x, height),
+                        time_alive: 2.0,
+                    },
+                );
+                continue;
+            }
+
+            if vel.y < 0.0 {
+                // Stop moving up when the unit hits a ceiling
+                if system_data
+                    .terrain
+                    .rect_collides(walk.bounds + *pos.0)
+                    .is_some()
+                {
+                    pos.0.y -= vel.y * dt;
+                    vel.y = 0.0;
+                }
+                continue;
+            }
 
             // Move the units if they collide with the ground in a loop until they don't touch the ground anymore
+            let mut landed = false;
             loop {
                 let hit_box = walk.bounds + *pos.0;
-                match terrain.rect_collides(hit_box) {
+                match system_data.terrain.rect_collides(hit_box) {
                     Some(_) => {
                         pos.0.y -= 1.0;
+                        landed = true;
                     }
                     None => break,
                 }
             }
+
+            if !landed {
+                continue;
+            }
+            walk.grounded = true;
+
+            // Calculate the height the unit fell from with the speed it hit the ground with
+            let fall_height = vel.y * vel.y / (2.0 * grav);
+            if fall_height > FALL_DAMAGE_HEIGHT
+                && reduce_unit_health(
+                    &system_data.entities,
+                    entity,
+                    health,
+                    system_data.armor.get(entity),
+                    Damage(
+                        (fall_height - FALL_DAMAGE_HEIGHT) * FALL_DAMAGE_PER_PIXEL,
+                        DamageType::Blunt,
+                    ),
+                )
+            {
+                system_data.updater.insert(
+                    system_data.entities.create(),
+                    FloatingText {
+                        text: "x".to_string(),
+                        pos: pos.0,
+                        time_alive: 2.0,
+                    },
+                );
+            }
+
+            vel.y = 0.0;
+            vel.x *= (1.0 - GROUND_FRICTION * dt).max(0.0);
         }
     }
 }
@@ -194,11 +471,19 @@ impl<'a> System<'a> for UnitResumeWalkingSystem {
 #[derive(SystemData)]
 pub struct UnitCollideSystemData<'a> {
     entities: Entities<'a>,
-    ally: ReadStorage<'a, Ally>,
+    updater: Read<'a, LazyUpdate>,
+    factions: Read<'a, Factions>,
+    faction: ReadStorage<'a, Faction>,
     pos: ReadStorage<'a, WorldPosition>,
     bb: ReadStorage<'a, BoundingBox>,
     dest: ReadStorage<'a, Destination>,
+    unit_type: ReadStorage<'a, UnitType>,
+    armor: ReadStorage<'a, Armor>,
     state: WriteStorage<'a, UnitState>,
+    walk: ReadStorage<'a, Walk>,
+    charge: WriteStorage<'a, Charge>,
+    vel: WriteStorage<'a, Velocity>,
+    health: WriteStorage<'a, Health>,
 }
 
 pub struct UnitCollideSystem;
@@ -240,8 +525,8 @@ impl<'a> System<'a> for UnitCollideSystem {
                 }
 
                 // Join a melee
-                let is_melee = if let Some(state) = system_data.state.get_mut(e2) {
-                    *state == UnitState::Melee
+                let (is_melee, is_routing) = if let Some(state) = system_data.state.get_mut(e2) {
+                    (*state == UnitState::Melee, *state == UnitState::Rout)
                 } else {
                     // Unit doesn't have a unit state?
                     panic!("Unit doesn't have a unit state");
@@ -260,11 +545,15 @@ impl<'a> System<'a> for UnitCollideSystem {
                     continue;
                 }
 
-                let is_ally1 = system_data.ally.get(e1).is_some();
-                let is_ally2 = system_data.ally.get(e2).is_some();
+                let relation = system_data
+                    .factions
+                    .relation(system_data.faction.get(e1), system_data.faction.get(e2));
 
-                if is_ally1 == is_ally2 {
-                    // If they are both allies or both enemies let one of them wait
+                if relation == Relation::Neutral || (relation == Relation::Allied && is_routing) {
+                    // Walk past neutral units and let fleeing units pass through
+                    continue;
+                } else if relation == Relation::Allied {
+                    // If they are allied let one of them wait
                     let dist1 = (dest1.0 - pos1.0.x).abs();
                     let dist2 = (dest2.0 - pos2.0.x).abs();
                     // Let the unit wait which is furthest away from the destination
@@ -277,12 +566,77 @@ impl<'a> System<'a> for UnitCollideSystem {
                         *state = UnitState::Wait;
                     }
                 } else {
-                    // If they are an ally and an enemy let them fight
+                    let is_light = |unit: Entity| {
+                        system_data
+                            .unit_type
+                            .get(unit)
+                            .is_some_and(|unit_type| unit_type.is_light())
+                    };
+                    let light1 = is_light(e1);
+                    let light2 = is_light(e2);
+
+                    // Light units can't stop a charge and are trampled by it
+                    let charging2 = match (system_data.charge.get(e2), system_data.walk.get(e2)) {
+                        (Some(charge), Some(walk)) => charge.is_charging(walk),
+                        _ => false,
+                    };
+                    if light1 && charging2 {
+                        continue;
+                    }
+
+                    let impact = match (system_data.charge.get_mut(e1), system_data.walk.get(e1)) {
+                        (Some(charge), Some(walk)) => charge.impact(walk, e2, light2),
+                        _ => None,
+                    };
+                    if let Some(damage) = impact {
+                        if damage > 0.0 {
+                            let dir = (dest1.0 - pos1.0.x).signum();
+                            if let Some(vel) = system_data.vel.get_mut(e2) {
+                                vel.x += dir * damage * CHARGE_KNOCKBACK;
+                                vel.y -= damage * CHARGE_KNOCKBACK;
+                            }
+
+                            if let Some(health) = system_data.health.get_mut(e2) {
+                                if reduce_unit_health(
+                                    &system_data.entities,
+                                    e2,
+                                    health,
+                                    system_data.armor.get(e2),
+                                    Damage(damage, DamageType::Blunt),
+                                ) {
+                                    system_data.updater.insert(
+                                        system_data.entities.create(),
+                                        FloatingText {
+                                            text: "x".to_string(),
+                                            pos: pos2.0,
+                                            time_alive: 2.0,
+                                        },
+                                    );
+                                }
+                            }
+
+                            Emitter::spawn(
+                                &system_data.entities,
+                                &system_data.updater,
+                                BLOOD,
+                                pos2.0,
+                            );
+                        }
+
+                        if light2 {
+                            // Ride on through the unit
+                            continue;
+                        }
+                    }
+
+                    // If they are hostile let them fight, routing units only get hit
                     if let Some(state) = system_data.state.get_mut(e1) {
                         *state = UnitState::Melee;
                     }
                     if let Some(state) = system_data.state.get_mut(e2) {
-                        *state = UnitState::Melee;
+                        if *state != UnitState::Rout {
+                            *state = UnitState::Melee;
+                        }
                     }
                     break;
                 }
@@ -291,13 +645,15 @@ impl<'a> System<'a> for UnitCollideSystem {
     }
 }
 
+/// Damage the unit through its armor, returns whether the unit died.
 pub fn reduce_unit_health<'a>(
     entities: &'a Entities,
     unit: Entity,
     health: &'a mut Health,
-    dmg: f64,
+    armor: Option<&Armor>,
+    dmg: Damage,
 ) -> bool {
-    health.0 -= dmg;
+    health.0 -= armored_damage(armor, dmg.0, dmg.1);
     if health.0 <= 0.0 {
         let _ = entities.delete(unit);
 
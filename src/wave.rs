@@ -0,0 +1,174 @@
+use specs::prelude::*;
+use std::collections::VecDeque;
+
+use super::*;
+
+/// When a wave starts attacking.
+#[derive(Debug, Copy, Clone)]
+pub enum WaveStart {
+    /// Seconds after the level started.
+    At(f64),
+    /// Seconds after all the units of the previous waves attacking the castle are killed.
+    Cleared(f64),
+}
+
+/// A group of enemy units attacking together.
+#[derive(Debug, Clone)]
+pub struct Wave {
+    pub start: WaveStart,
+    /// The faction the units fight for.
+    pub faction: Faction,
+    /// The type and amount of units, spawned in order.
+    pub units: Vec<(UnitType, usize)>,
+    pub pos: Point,
+    /// The seconds between spawning each unit.
+    pub interval: f64,
+}
+
+impl Wave {
+    /// Take the next unit to spawn from the composition.
+    fn next_unit(&mut self) -> Option<UnitType> {
+        let (unit_type, amount) = self.units.first_mut()?;
+        let unit_type = *unit_type;
+
+        *amount = amount.saturating_sub(1);
+        if *amount == 0 {
+            self.units.remove(0);
+        }
+
+        Some(unit_type)
+    }
+}
+
+/// The schedule of the enemy waves of a level.
+#[derive(Debug, Default)]
+pub struct WaveSpawner {
+    waves: VecDeque<Wave>,
+    total: usize,
+
+    /// The seconds since the level started.
+    time: f64,
+    /// The wave that is spawning units.
+    current: Option<Wave>,
+    /// The first unit of every wave leads the others.
+    leader_spawned: bool,
+    spawn_left: f64,
+    /// The units spawned by the waves attacking the castle that are still alive.
+    alive: Vec<Entity>,
+    /// The time when the last unit of the waves was killed.
+    cleared_at: Option<f64>,
+}
+
+impl WaveSpawner {
+    pub fn new(waves: Vec<Wave>) -> Self {
+        WaveSpawner {
+            total: waves.len(),
+            waves: waves.into(),
+
+            // The level starts without enemies
+            cleared_at: Some(0.0),
+            ..WaveSpawner::default()
+        }
+    }
+
+    /// The seconds until the next wave starts, `None` if the wave is waiting for the field to be
+    /// cleared or there are no waves left.
+    pub fn next_wave_in(&self) -> Option<f64> {
+        if self.current.is_some() {
+            return None;
+        }
+
+        match self.waves.front()?.start {
+            WaveStart::At(time) => Some((time - self.time).max(0.0)),
+            WaveStart::Cleared(delay) => self
+                .cleared_at
+                .map(|cleared_at| (cleared_at + delay - self.time).max(0.0)),
+        }
+    }
+
+    /// Describe the state of the waves for the HUD.
+    pub fn label(&self) -> Option<String> {
+        let wave = self.total - self.waves.len();
+
+        if self.current.is_some() {
+            return Some(format!("Wave {}/{}", wave, self.total));
+        }
+
+        self.waves.front()?;
+        Some(match self.next_wave_in() {
+            Some(time) => format!("Wave {}/{} in {}", wave + 1, self.total, time.ceil()),
+            // Waiting until all enemies are killed
+            None => format!("Wave {}/{} after clearing", wave + 1, self.total),
+        })
+    }
+}
+
+pub struct WaveSystem;
+impl<'a> System<'a> for WaveSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        ReadExpect<'a, Images>,
+        Read<'a, Factions>,
+        Write<'a, WaveSpawner>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, (entities, dt, images, factions, mut spawner, updater): Self::SystemData) {
+        let dt = dt.to_seconds();
+
+        spawner.time += dt;
+
+        spawner.alive.retain(|unit| entities.is_alive(*unit));
+        if spawner.current.is_none() && spawner.alive.is_empty() && spawner.cleared_at.is_none() {
+            spawner.cleared_at = Some(spawner.time);
+        }
+
+        // Start the next wave
+        if spawner.next_wave_in() == Some(0.0) {
+            spawner.current = spawner.waves.pop_front();
+            spawner.spawn_left = 0.0;
+            spawner.cleared_at = None;
+            spawner.leader_spawned = false;
+        }
+
+        // Spawn the units of the current wave one by one
+        spawner.spawn_left -= dt;
+        while spawner.spawn_left <= 0.0 {
+            let wave = match spawner.current.as_mut() {
+                Some(wave) => wave,
+                None => break,
+            };
+
+            let (unit_type, faction, pos, interval) = match wave.next_unit() {
+                Some(unit_type) => (unit_type, wave.faction, wave.pos, wave.interval),
+                None => {
+                    spawner.current = None;
+                    break;
+                }
+            };
+
+            let side = factions.side(faction);
+            let mut builder = archetype(
+                updater.create_entity(&entities),
+                &images,
+                unit_type,
+                side,
+                pos,
+            );
+            if !spawner.leader_spawned {
+                builder = builder.with(Aura::new(40.0, vec![AuraEffect::Morale(8.0)]));
+                spawner.leader_spawned = true;
+            }
+            let entity = builder.build();
+
+            // Raiders roam around and fall back to their camp instead of attacking the castle, the
+            // next wave doesn't wait for them to be killed
+            if !side.raids {
+                spawner.alive.push(entity);
+            }
+
+            spawner.spawn_left += interval;
+        }
+    }
+}